@@ -1,6 +1,7 @@
 #![no_main]
 
 use borsh::{BorshDeserialize, BorshSerialize};
+use spin_sdk::spin_primitives::{Schedule, ScheduleTime};
 
 #[derive(BorshDeserialize, BorshSerialize)]
 struct State {
@@ -27,4 +28,22 @@ impl Contract {
 
         env::set_state(String::from("root"), state);
     }
+
+    /// Calls `add` every `interval` blocks, `runs` times, starting `interval` blocks from now.
+    pub fn schedule_add(input: (u64, u32)) {
+        let interval = input.0;
+        let runs = input.1;
+
+        env::schedule_call(
+            env::contract(),
+            "add".to_string(),
+            1_000_000,
+            (),
+            Schedule::recurring(
+                ScheduleTime::BlockHeight(env::block_height() + interval),
+                interval,
+                runs,
+            ),
+        );
+    }
 }
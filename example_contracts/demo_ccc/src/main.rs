@@ -1,5 +1,7 @@
 #![no_main]
 
+use spin_sdk::spin_primitives::{Callback, PromiseResult};
+
 struct Contract;
 
 #[spin_sdk_macros::contract]
@@ -33,6 +35,64 @@ impl Contract {
         );
     }
 
+    /// Transfers with the fallback token if the transfer with the first one fails.
+    pub fn transfer_token_with_fallback(input: (AccountId, AccountId, AccountId, u128)) {
+        let token_account = input.0;
+        let fallback_token_account = input.1;
+        let recipient = input.2;
+        let amount = input.3;
+
+        let transferred: Result<(), _> = env::try_cross_contract_call(
+            token_account.clone(),
+            "transfer".to_string(),
+            1_000_000,
+            (recipient.clone(), amount),
+        );
+
+        let used_token_account = match transferred {
+            Ok(()) => token_account,
+            Err(_) => {
+                let _: () = env::cross_contract_call(
+                    fallback_token_account.clone(),
+                    "transfer".to_string(),
+                    1_000_000,
+                    (recipient, amount),
+                );
+                fallback_token_account
+            }
+        };
+
+        env::commit(used_token_account);
+    }
+
+    pub fn transfer_token_async(input: (AccountId, AccountId, u128)) {
+        let token_account = input.0;
+        let recipient = input.1;
+        let amount = input.2;
+
+        env::async_cross_contract_call(
+            token_account,
+            "transfer".to_string(),
+            1_000_000,
+            (recipient.clone(), amount),
+            Some(Callback::new(
+                "on_transfer".to_string(),
+                recipient,
+                1_000_000,
+            )),
+        );
+    }
+
+    pub fn on_transfer(input: (PromiseResult, AccountId)) {
+        assert!(
+            env::caller() == env::contract(),
+            "Callback can only be called by the contract itself"
+        );
+
+        let transferred = matches!(input.0, PromiseResult::Successful(_));
+        env::commit((input.1, transferred));
+    }
+
     pub fn hello(name: String) {
         let result = format!("Hello, {}!", name);
         env::commit(result);
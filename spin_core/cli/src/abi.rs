@@ -0,0 +1,328 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use std::path::Path;
+
+/// Contract ABI describing Borsh layouts of method arguments and outputs.
+///
+/// ```json
+/// {
+///   "methods": [
+///     {
+///       "name": "transfer",
+///       "args": [
+///         { "name": "recipient", "type": "account_id" },
+///         { "name": "amount", "type": "u128" }
+///       ]
+///     },
+///     { "name": "balance_of", "args": [{ "name": "address", "type": "account_id" }], "output": "u128" }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct ContractAbi {
+    pub methods: Vec<MethodAbi>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MethodAbi {
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<FieldAbi>,
+    #[serde(default)]
+    pub output: Option<AbiType>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FieldAbi {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: AbiType,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AbiType {
+    Unit,
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    String,
+    AccountId,
+    /// `Vec<u8>`, written as a hex string.
+    Bytes,
+    /// `[T; N]`, `[u8; N]` is written as a hex string.
+    Array(Box<AbiType>, usize),
+    Vec(Box<AbiType>),
+    Option(Box<AbiType>),
+    Tuple(Vec<AbiType>),
+    Struct(Vec<FieldAbi>),
+}
+
+impl ContractAbi {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::read(path).with_context(|| format!("Can't read ABI {:?}", path))?;
+        serde_json::from_slice(&file).with_context(|| format!("Can't parse ABI {:?}", path))
+    }
+
+    pub fn method(&self, name: &str) -> Result<&MethodAbi> {
+        self.methods
+            .iter()
+            .find(|method| method.name == name)
+            .ok_or_else(|| anyhow!("Method {:?} not found in ABI", name))
+    }
+}
+
+impl MethodAbi {
+    /// Encodes JSON arguments, given either as an object keyed by argument names
+    /// or as an array of positional arguments.
+    ///
+    /// Multiple arguments are encoded as a tuple, which is how contracts take them.
+    pub fn encode_args(&self, args: &Value) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+
+        match args {
+            Value::Object(map) => {
+                for field in self.args.iter() {
+                    let value = map
+                        .get(&field.name)
+                        .ok_or_else(|| anyhow!("Missing argument {:?}", field.name))?;
+                    encode(&field.ty, value, &mut out)
+                        .with_context(|| format!("Invalid argument {:?}", field.name))?;
+                }
+            }
+            Value::Array(values) if values.len() == self.args.len() => {
+                for (field, value) in self.args.iter().zip(values) {
+                    encode(&field.ty, value, &mut out)
+                        .with_context(|| format!("Invalid argument {:?}", field.name))?;
+                }
+            }
+            _ => bail!(
+                "Expected an object or an array of {} arguments",
+                self.args.len()
+            ),
+        }
+
+        Ok(out)
+    }
+
+    /// Decodes the Borsh-encoded output into JSON, `None` if the ABI doesn't describe it.
+    pub fn decode_output(&self, output: &[u8]) -> Result<Option<Value>> {
+        let ty = match &self.output {
+            Some(ty) => ty,
+            None => return Ok(None),
+        };
+
+        let mut input = output;
+        let value = decode(ty, &mut input)?;
+        if !input.is_empty() {
+            bail!("{} unexpected trailing bytes in output", input.len());
+        }
+
+        Ok(Some(value))
+    }
+}
+
+fn parse_u128(value: &Value) -> Result<u128> {
+    match value {
+        Value::Number(n) => n.as_u64().map(u128::from),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| anyhow!("Expected unsigned integer, got {}", value))
+}
+
+fn parse_i128(value: &Value) -> Result<i128> {
+    match value {
+        Value::Number(n) => n.as_i64().map(i128::from),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| anyhow!("Expected integer, got {}", value))
+}
+
+fn parse_hex(value: &Value) -> Result<Vec<u8>> {
+    let s = value
+        .as_str()
+        .ok_or_else(|| anyhow!("Expected hex string, got {}", value))?;
+    Ok(hex::decode(s.trim_start_matches("0x"))?)
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) -> Result<()> {
+    out.extend_from_slice(&u32::try_from(len)?.to_le_bytes());
+    Ok(())
+}
+
+fn encode(ty: &AbiType, value: &Value, out: &mut Vec<u8>) -> Result<()> {
+    match ty {
+        AbiType::Unit => {}
+        AbiType::Bool => {
+            let b = value
+                .as_bool()
+                .ok_or_else(|| anyhow!("Expected bool, got {}", value))?;
+            out.push(b as u8);
+        }
+        AbiType::U8 => out.extend_from_slice(&u8::try_from(parse_u128(value)?)?.to_le_bytes()),
+        AbiType::U16 => out.extend_from_slice(&u16::try_from(parse_u128(value)?)?.to_le_bytes()),
+        AbiType::U32 => out.extend_from_slice(&u32::try_from(parse_u128(value)?)?.to_le_bytes()),
+        AbiType::U64 => out.extend_from_slice(&u64::try_from(parse_u128(value)?)?.to_le_bytes()),
+        AbiType::U128 => out.extend_from_slice(&parse_u128(value)?.to_le_bytes()),
+        AbiType::I8 => out.extend_from_slice(&i8::try_from(parse_i128(value)?)?.to_le_bytes()),
+        AbiType::I16 => out.extend_from_slice(&i16::try_from(parse_i128(value)?)?.to_le_bytes()),
+        AbiType::I32 => out.extend_from_slice(&i32::try_from(parse_i128(value)?)?.to_le_bytes()),
+        AbiType::I64 => out.extend_from_slice(&i64::try_from(parse_i128(value)?)?.to_le_bytes()),
+        AbiType::I128 => out.extend_from_slice(&parse_i128(value)?.to_le_bytes()),
+        AbiType::String | AbiType::AccountId => {
+            let s = value
+                .as_str()
+                .ok_or_else(|| anyhow!("Expected string, got {}", value))?;
+            encode_len(s.len(), out)?;
+            out.extend_from_slice(s.as_bytes());
+        }
+        AbiType::Bytes => {
+            let bytes = parse_hex(value)?;
+            encode_len(bytes.len(), out)?;
+            out.extend_from_slice(&bytes);
+        }
+        AbiType::Array(item, len) => match (item.as_ref(), value) {
+            (AbiType::U8, Value::String(_)) => {
+                let bytes = parse_hex(value)?;
+                if bytes.len() != *len {
+                    bail!("Expected {} bytes, got {}", len, bytes.len());
+                }
+                out.extend_from_slice(&bytes);
+            }
+            (_, Value::Array(values)) if values.len() == *len => {
+                for value in values {
+                    encode(item, value, out)?;
+                }
+            }
+            _ => bail!("Expected array of {} items, got {}", len, value),
+        },
+        AbiType::Vec(item) => {
+            let values = value
+                .as_array()
+                .ok_or_else(|| anyhow!("Expected array, got {}", value))?;
+            encode_len(values.len(), out)?;
+            for value in values {
+                encode(item, value, out)?;
+            }
+        }
+        AbiType::Option(item) => {
+            if value.is_null() {
+                out.push(0);
+            } else {
+                out.push(1);
+                encode(item, value, out)?;
+            }
+        }
+        AbiType::Tuple(items) => {
+            let values = value
+                .as_array()
+                .filter(|values| values.len() == items.len())
+                .ok_or_else(|| anyhow!("Expected tuple of {} items, got {}", items.len(), value))?;
+            for (item, value) in items.iter().zip(values) {
+                encode(item, value, out)?;
+            }
+        }
+        AbiType::Struct(fields) => {
+            let map = value
+                .as_object()
+                .ok_or_else(|| anyhow!("Expected object, got {}", value))?;
+            for field in fields {
+                let value = map
+                    .get(&field.name)
+                    .ok_or_else(|| anyhow!("Missing field {:?}", field.name))?;
+                encode(&field.ty, value, out)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if input.len() < len {
+        bail!("Unexpected end of output");
+    }
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    Ok(head)
+}
+
+fn take_array<const N: usize>(input: &mut &[u8]) -> Result<[u8; N]> {
+    Ok(take(input, N)?.try_into().unwrap())
+}
+
+fn decode_len(input: &mut &[u8]) -> Result<usize> {
+    Ok(u32::from_le_bytes(take_array(input)?) as usize)
+}
+
+fn decode(ty: &AbiType, input: &mut &[u8]) -> Result<Value> {
+    let value = match ty {
+        AbiType::Unit => Value::Null,
+        AbiType::Bool => Value::Bool(take(input, 1)?[0] != 0),
+        AbiType::U8 => Value::from(u8::from_le_bytes(take_array(input)?)),
+        AbiType::U16 => Value::from(u16::from_le_bytes(take_array(input)?)),
+        AbiType::U32 => Value::from(u32::from_le_bytes(take_array(input)?)),
+        AbiType::U64 => Value::from(u64::from_le_bytes(take_array(input)?)),
+        AbiType::U128 => Value::from(u128::from_le_bytes(take_array(input)?).to_string()),
+        AbiType::I8 => Value::from(i8::from_le_bytes(take_array(input)?)),
+        AbiType::I16 => Value::from(i16::from_le_bytes(take_array(input)?)),
+        AbiType::I32 => Value::from(i32::from_le_bytes(take_array(input)?)),
+        AbiType::I64 => Value::from(i64::from_le_bytes(take_array(input)?)),
+        AbiType::I128 => Value::from(i128::from_le_bytes(take_array(input)?).to_string()),
+        AbiType::String | AbiType::AccountId => {
+            let len = decode_len(input)?;
+            Value::from(String::from_utf8(take(input, len)?.to_vec())?)
+        }
+        AbiType::Bytes => {
+            let len = decode_len(input)?;
+            Value::from(hex::encode(take(input, len)?))
+        }
+        AbiType::Array(item, len) => match item.as_ref() {
+            AbiType::U8 => Value::from(hex::encode(take(input, *len)?)),
+            _ => Value::Array(
+                (0..*len)
+                    .map(|_| decode(item, input))
+                    .collect::<Result<_>>()?,
+            ),
+        },
+        AbiType::Vec(item) => {
+            let len = decode_len(input)?;
+            Value::Array(
+                (0..len)
+                    .map(|_| decode(item, input))
+                    .collect::<Result<_>>()?,
+            )
+        }
+        AbiType::Option(item) => match take(input, 1)?[0] {
+            0 => Value::Null,
+            _ => decode(item, input)?,
+        },
+        AbiType::Tuple(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| decode(item, input))
+                .collect::<Result<_>>()?,
+        ),
+        AbiType::Struct(fields) => {
+            let mut map = Map::new();
+            for field in fields {
+                map.insert(field.name.clone(), decode(&field.ty, input)?);
+            }
+            Value::Object(map)
+        }
+    };
+
+    Ok(value)
+}
@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Result};
+use clap::{Args, Subcommand};
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::json;
+
+use spin_node::rpc::AccessKeyRequest;
+use spin_primitives::{
+    AccessKeyPermission, AccountId, Action, FunctionCallPermission, PublicKey, Signature,
+    SignedTransaction, Transaction,
+};
+
+use crate::Client;
+
+#[derive(Subcommand)]
+pub enum KeysCommand {
+    /// Generate a new key pair
+    Generate,
+    /// Print an access key of an account
+    Show {
+        account: AccountId,
+        #[arg(value_parser = parse_public_key)]
+        public_key: PublicKey,
+    },
+    /// Add an access key to the signer account, a function call key if `--contract` is set
+    Add {
+        #[arg(value_parser = parse_public_key)]
+        public_key: PublicKey,
+
+        /// Contract the key may call
+        #[arg(long)]
+        contract: Option<AccountId>,
+
+        /// Methods the key may call, any method of the contract if not set
+        #[arg(long, requires = "contract", value_delimiter = ',')]
+        methods: Vec<String>,
+
+        /// Gas the key may spend, unlimited if not set
+        #[arg(long, requires = "contract")]
+        allowance: Option<u64>,
+
+        #[command(flatten)]
+        key: SignerArgs,
+    },
+    /// Delete an access key of the signer account
+    Delete {
+        #[arg(value_parser = parse_public_key)]
+        public_key: PublicKey,
+
+        #[command(flatten)]
+        key: SignerArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct SignerArgs {
+    #[arg(long)]
+    pub signer: AccountId,
+
+    /// Ed25519 secret key of the signer as hex
+    #[arg(
+        long,
+        env = "SPIN_SECRET_KEY",
+        value_parser = parse_secret_key,
+        hide_env_values = true
+    )]
+    pub secret_key: SigningKey,
+}
+
+pub fn parse_secret_key(s: &str) -> Result<SigningKey> {
+    let bytes: [u8; 32] = hex::decode(s.trim_start_matches("0x"))?
+        .try_into()
+        .map_err(|_| anyhow!("Expected 32 bytes"))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+fn parse_public_key(s: &str) -> Result<PublicKey> {
+    let bytes: [u8; 32] = hex::decode(s.trim_start_matches("0x"))?
+        .try_into()
+        .map_err(|_| anyhow!("Expected 32 bytes"))?;
+    Ok(PublicKey(bytes))
+}
+
+/// Signs the actions with the next nonce of the signer's access key.
+pub fn sign(
+    client: &Client,
+    signer: &AccountId,
+    secret_key: &SigningKey,
+    actions: Vec<Action>,
+) -> Result<SignedTransaction> {
+    let public_key = PublicKey(secret_key.verifying_key().to_bytes());
+
+    let access_key = client
+        .access_key(AccessKeyRequest {
+            account: signer.clone(),
+            public_key: public_key.clone(),
+        })?
+        .ok_or_else(|| {
+            anyhow!(
+                "Access key {} of {} not found",
+                hex::encode(public_key.0),
+                signer
+            )
+        })?;
+
+    let transaction = Transaction {
+        signer: signer.clone(),
+        public_key,
+        nonce: access_key.nonce + 1,
+        actions,
+    };
+    let signature = Signature(secret_key.sign(&transaction.hash()).to_bytes());
+
+    Ok(SignedTransaction {
+        transaction,
+        signature,
+    })
+}
+
+pub fn run(client: &Client, command: KeysCommand) -> Result<()> {
+    match command {
+        KeysCommand::Generate => {
+            let secret_key = SigningKey::generate(&mut rand::rngs::OsRng);
+            let printed = json!({
+                "secret_key": hex::encode(secret_key.to_bytes()),
+                "public_key": hex::encode(secret_key.verifying_key().to_bytes()),
+            });
+            println!("{}", serde_json::to_string_pretty(&printed)?);
+        }
+        KeysCommand::Show {
+            account,
+            public_key,
+        } => {
+            let access_key = client.access_key(AccessKeyRequest {
+                account,
+                public_key,
+            })?;
+            println!("{}", serde_json::to_string_pretty(&access_key)?);
+        }
+        KeysCommand::Add {
+            public_key,
+            contract,
+            methods,
+            allowance,
+            key,
+        } => {
+            let permission = match contract {
+                Some(receiver) => AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                    receiver,
+                    method_names: methods,
+                    allowance,
+                }),
+                None => AccessKeyPermission::FullAccess,
+            };
+
+            let transaction = sign(
+                client,
+                &key.signer,
+                &key.secret_key,
+                vec![Action::AddKey {
+                    public_key,
+                    permission,
+                }],
+            )?;
+            client.send_transaction(transaction)?;
+            println!("Key added");
+        }
+        KeysCommand::Delete { public_key, key } => {
+            let transaction = sign(
+                client,
+                &key.signer,
+                &key.secret_key,
+                vec![Action::DeleteKey { public_key }],
+            )?;
+            client.send_transaction(transaction)?;
+            println!("Key deleted");
+        }
+    }
+
+    Ok(())
+}
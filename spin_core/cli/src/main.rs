@@ -0,0 +1,588 @@
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use serde_json::{json, Value};
+
+use ed25519_dalek::SigningKey;
+use spin_node::{
+    client::RpcClient,
+    proving::TransactionRecord,
+    remote_prover::RemoteProver,
+    rpc::{
+        AccessKeyRequest, AccountRequest, BlockProofRequest, BlockProofResponse, CallOutcome,
+        CallRequest, StorageEntry, StorageProofRequest, StorageProofResponse, StorageRequest,
+        TransactionOutcome, TransactionProofsResponse, TransactionRequest,
+    },
+    Node,
+};
+use spin_primitives::{
+    proof::StateProof, AccessKey, Account, AccountId, Action, BlockInfo, ContractCall,
+    FunctionCall, SignedTransaction,
+};
+use spin_runtime::config::RuntimeConfig;
+use spin_verifier::DEV_MODE_ENV;
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+mod abi;
+mod keys;
+
+use abi::{ContractAbi, MethodAbi};
+use keys::{KeysCommand, SignerArgs};
+
+#[derive(Parser)]
+#[command(name = "spin", about = "Command-line client for Spin contracts")]
+struct Cli {
+    /// Node JSON-RPC URL, e.g. http://127.0.0.1:3030. Without it, the local runtime is used.
+    #[arg(long, global = true)]
+    rpc: Option<String>,
+
+    /// Runtime config file of the local runtime, the default config if not set
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// State directory of the local runtime, overriding the state backend of the config
+    #[arg(long, global = true)]
+    state: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+impl Cli {
+    fn runtime_config(&self) -> Result<RuntimeConfig> {
+        RuntimeConfig::resolve(self.config.as_deref(), self.state.as_deref())
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Deploy a contract ELF to an account, creating it as a sub-account of the signer if needed
+    Deploy {
+        account: AccountId,
+        elf: PathBuf,
+
+        #[command(flatten)]
+        key: SignerArgs,
+    },
+    /// Sign a contract call with the signer's key and commit its state changes
+    Call(CallArgs),
+    /// Call a contract method without committing its state changes
+    View(CallArgs),
+    /// Execute a call on the local state without committing it and write the proof of its call
+    /// tree to a bundle file
+    Prove {
+        #[command(flatten)]
+        call: CallArgs,
+
+        /// Proof bundle file to write
+        #[arg(long)]
+        out: PathBuf,
+
+        /// URL of a proving service, e.g. http://127.0.0.1:3040. Without it, calls are proven
+        /// in process.
+        #[arg(long)]
+        prover: Option<String>,
+
+        /// Make fake receipts instead of proving, for tests; refused on a production chain
+        #[arg(long, env = DEV_MODE_ENV, conflicts_with = "prover")]
+        dev_mode: bool,
+    },
+    /// Sign and send a transaction with the actions from a JSON file, which all revert if one fails
+    Batch {
+        file: PathBuf,
+
+        #[command(flatten)]
+        key: SignerArgs,
+    },
+    /// Print all storage keys and values of a contract
+    Storage {
+        contract: AccountId,
+        /// Block to read the storage at the end of, the latest state if not set
+        #[arg(long)]
+        height: Option<u64>,
+    },
+    /// Fetch the Merkle proof of a storage value and verify it
+    Proof {
+        contract: AccountId,
+        key: String,
+
+        /// State root as hex to prove against, the latest one if not set
+        #[arg(long)]
+        root: Option<String>,
+    },
+    /// Print the balance and storage usage of an account
+    Account {
+        account: AccountId,
+        /// Block to read the account at the end of, the latest state if not set
+        #[arg(long)]
+        height: Option<u64>,
+    },
+    /// Transfer balance of the signer to another account
+    Transfer {
+        receiver: AccountId,
+        amount: u128,
+
+        #[command(flatten)]
+        key: SignerArgs,
+    },
+    /// Print an executed transaction with the status of its proof
+    Transaction {
+        /// Transaction hash as hex
+        hash: String,
+        /// Directory to write the proof bundles of its function calls to, once it is proven
+        #[arg(long)]
+        proofs: Option<PathBuf>,
+    },
+    /// Print the height and timestamp of the latest block
+    Block,
+    /// Write the proofs of all function calls of a finished block to a block proof file, once
+    /// they are proven
+    BlockProof {
+        height: u64,
+        /// Block proof file to write
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Build the initial state from a genesis file and print its root
+    Genesis { file: PathBuf },
+    /// Export or import the local state
+    #[command(subcommand)]
+    Snapshot(SnapshotCommand),
+    /// Manage access keys
+    #[command(subcommand)]
+    Keys(KeysCommand),
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommand {
+    /// Write the state to a snapshot file
+    Export {
+        file: PathBuf,
+        /// Block to export the state of, the latest state if not set
+        #[arg(long)]
+        height: Option<u64>,
+    },
+    /// Load a snapshot file into an empty state and print its root
+    Import { file: PathBuf },
+}
+
+#[derive(Args)]
+struct CallArgs {
+    contract: AccountId,
+    method: String,
+
+    /// Arguments as a JSON object or array, encoded to Borsh using the ABI
+    #[arg(long, requires = "abi", conflicts_with = "args_hex")]
+    args: Option<String>,
+
+    /// Borsh-encoded arguments as hex
+    #[arg(long)]
+    args_hex: Option<String>,
+
+    /// Contract ABI, used to encode arguments and decode the output
+    #[arg(long)]
+    abi: Option<PathBuf>,
+
+    #[arg(long)]
+    signer: AccountId,
+
+    /// Ed25519 secret key of the signer as hex, required to sign calls
+    #[arg(
+        long,
+        env = "SPIN_SECRET_KEY",
+        value_parser = keys::parse_secret_key,
+        hide_env_values = true
+    )]
+    secret_key: Option<SigningKey>,
+
+    #[arg(long, default_value_t = 100_000_000)]
+    gas: u64,
+
+    /// Block to run a view call at the end of, the latest state if not set
+    #[arg(long)]
+    height: Option<u64>,
+}
+
+enum Client {
+    Local(Node),
+    Rpc(RpcClient),
+}
+
+impl Client {
+    fn send_transaction(&self, transaction: SignedTransaction) -> Result<TransactionOutcome> {
+        match self {
+            Client::Local(node) => node.send_transaction(transaction),
+            Client::Rpc(client) => client.send_transaction(transaction),
+        }
+    }
+
+    fn view(&self, request: CallRequest) -> Result<CallOutcome> {
+        match self {
+            Client::Local(node) => node.view(request),
+            Client::Rpc(client) => client.view(request),
+        }
+    }
+
+    fn storage(&self, request: StorageRequest) -> Result<Vec<StorageEntry>> {
+        match self {
+            Client::Local(node) => node.storage(request),
+            Client::Rpc(client) => client.storage(request),
+        }
+    }
+
+    fn storage_proof(&self, request: StorageProofRequest) -> Result<StorageProofResponse> {
+        match self {
+            Client::Local(node) => node.storage_proof(request),
+            Client::Rpc(client) => client.storage_proof(request),
+        }
+    }
+
+    fn account(&self, request: AccountRequest) -> Result<Option<Account>> {
+        match self {
+            Client::Local(node) => node.account(request),
+            Client::Rpc(client) => client.account(request),
+        }
+    }
+
+    fn block(&self) -> Result<BlockInfo> {
+        match self {
+            Client::Local(node) => node.block(),
+            Client::Rpc(client) => client.block(),
+        }
+    }
+
+    fn transaction(&self, request: TransactionRequest) -> Result<Option<TransactionRecord>> {
+        match self {
+            Client::Local(node) => node.transaction(request),
+            Client::Rpc(client) => client.transaction(request),
+        }
+    }
+
+    fn transaction_proofs(&self, request: TransactionRequest) -> Result<TransactionProofsResponse> {
+        match self {
+            Client::Local(node) => node.transaction_proofs(request),
+            Client::Rpc(client) => client.transaction_proofs(request),
+        }
+    }
+
+    fn block_proof(&self, request: BlockProofRequest) -> Result<BlockProofResponse> {
+        match self {
+            Client::Local(node) => node.block_proof(request),
+            Client::Rpc(client) => client.block_proof(request),
+        }
+    }
+
+    fn access_key(&self, request: AccessKeyRequest) -> Result<Option<AccessKey>> {
+        match self {
+            Client::Local(node) => node.access_key(request),
+            Client::Rpc(client) => client.access_key(request),
+        }
+    }
+}
+
+fn install_tracing() {
+    use tracing_subscriber::{fmt, prelude::*, registry, EnvFilter};
+
+    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "warn".to_owned());
+
+    let main_layer = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_filter(EnvFilter::from(filter));
+
+    registry().with(main_layer).init();
+}
+
+fn main() -> Result<()> {
+    install_tracing();
+
+    let cli = Cli::parse();
+
+    if let Command::Genesis { file } = &cli.command {
+        if cli.rpc.is_some() {
+            bail!("Genesis can only be built for the local state");
+        }
+        let root = Node::open(cli.runtime_config()?)?.init_genesis(file)?;
+        println!("{}", hex::encode(root));
+        return Ok(());
+    }
+
+    if let Command::Snapshot(command) = &cli.command {
+        if cli.rpc.is_some() {
+            bail!("Snapshots can only be made of the local state");
+        }
+        let node = Node::open(cli.runtime_config()?)?;
+        match command {
+            SnapshotCommand::Export { file, height } => {
+                let snapshot = node.export_snapshot(file, *height)?;
+                let printed = json!({
+                    "root": hex::encode(snapshot.root),
+                    "block": snapshot.block,
+                    "entries": snapshot.entries.len(),
+                });
+                println!("{}", serde_json::to_string_pretty(&printed)?);
+            }
+            SnapshotCommand::Import { file } => {
+                println!("{}", hex::encode(node.import_snapshot(file)?));
+            }
+        }
+        return Ok(());
+    }
+
+    if let Command::Prove {
+        call,
+        out,
+        prover,
+        dev_mode,
+    } = &cli.command
+    {
+        if cli.rpc.is_some() {
+            bail!("Calls can only be proven on the local state");
+        }
+        let abi = call.abi.as_deref().map(ContractAbi::load).transpose()?;
+        let method = abi
+            .as_ref()
+            .map(|abi| abi.method(&call.method))
+            .transpose()?;
+        let args = encode_args(call, method)?;
+
+        let mut node = Node::open(cli.runtime_config()?)?;
+        if let Some(url) = prover {
+            node = node.with_prover(Arc::new(RemoteProver::new(url.clone())));
+        }
+        if *dev_mode {
+            node = node.with_dev_mode()?;
+        }
+        let bundle = node.prove(CallRequest {
+            signer: call.signer.clone(),
+            contract: call.contract.clone(),
+            method: call.method.clone(),
+            args,
+            attached_gas: call.gas,
+            block_height: call.height,
+        })?;
+        bundle.write(out)?;
+
+        let image_ids = bundle
+            .image_ids()?
+            .into_iter()
+            .map(|(contract, image_id)| (contract.to_string(), image_id.to_string()))
+            .collect::<BTreeMap<_, _>>();
+        let printed = json!({
+            "bundle": out,
+            "image_ids": image_ids,
+            "fake": bundle.call.is_fake(),
+        });
+        println!("{}", serde_json::to_string_pretty(&printed)?);
+        return Ok(());
+    }
+
+    let client = match cli.rpc {
+        Some(url) => Client::Rpc(RpcClient::new(url)),
+        None => Client::Local(Node::open(cli.runtime_config()?)?),
+    };
+
+    match cli.command {
+        Command::Deploy { account, elf, key } => {
+            let code = std::fs::read(&elf).with_context(|| format!("Can't read {:?}", elf))?;
+            let exists = client
+                .account(AccountRequest {
+                    account: account.clone(),
+                    block_height: None,
+                })?
+                .is_some();
+
+            let mut actions = Vec::new();
+            if !exists {
+                actions.push(Action::CreateAccount {
+                    account: account.clone(),
+                    public_key: None,
+                });
+            }
+            actions.push(Action::DeployContract { account, code });
+            let transaction = keys::sign(&client, &key.signer, &key.secret_key, actions)?;
+            client.send_transaction(transaction)?;
+            println!("Deployed {:?}", elf);
+        }
+        Command::Call(args) => call(&client, args, false)?,
+        Command::View(args) => call(&client, args, true)?,
+        Command::Batch { file, key } => {
+            let actions: Vec<Action> = serde_json::from_slice(
+                &std::fs::read(&file).with_context(|| format!("Can't read {:?}", file))?,
+            )
+            .context("Invalid actions")?;
+            let transaction = keys::sign(&client, &key.signer, &key.secret_key, actions)?;
+            let outcome = client.send_transaction(transaction)?;
+            println!("{}", serde_json::to_string_pretty(&outcome)?);
+        }
+        Command::Storage { contract, height } => {
+            let entries = client.storage(StorageRequest {
+                contract,
+                block_height: height,
+            })?;
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+        }
+        Command::Proof {
+            contract,
+            key,
+            root,
+        } => {
+            let response = client.storage_proof(StorageProofRequest {
+                contract: contract.clone(),
+                key: key.clone(),
+                root,
+            })?;
+
+            let proof = StateProof::try_from_bytes(hex::decode(&response.proof)?)?;
+            let root: [u8; 32] = hex::decode(&response.root)?
+                .try_into()
+                .map_err(|_| anyhow!("State root must be 32 bytes"))?;
+            let value = response.value.as_deref().map(hex::decode).transpose()?;
+            proof
+                .verify_storage(&root, &contract, &key, value.as_deref())
+                .context("Invalid storage proof")?;
+
+            let printed = json!({
+                "root": response.root,
+                "value": response.value,
+                "proof": response.proof,
+                "verified": true,
+            });
+            println!("{}", serde_json::to_string_pretty(&printed)?);
+        }
+        Command::Account { account, height } => {
+            let account = client.account(AccountRequest {
+                account,
+                block_height: height,
+            })?;
+            println!("{}", serde_json::to_string_pretty(&account)?);
+        }
+        Command::Transfer {
+            receiver,
+            amount,
+            key,
+        } => {
+            let transaction = keys::sign(
+                &client,
+                &key.signer,
+                &key.secret_key,
+                vec![Action::Transfer { receiver, amount }],
+            )?;
+            client.send_transaction(transaction)?;
+            println!("Transferred {}", amount);
+        }
+        Command::Transaction { hash, proofs } => {
+            let record = client
+                .transaction(TransactionRequest { hash: hash.clone() })?
+                .ok_or_else(|| anyhow!("Transaction {} not found", hash))?;
+            println!("{}", serde_json::to_string_pretty(&record)?);
+
+            if let Some(dir) = proofs {
+                let response = client.transaction_proofs(TransactionRequest { hash })?;
+                std::fs::create_dir_all(&dir)?;
+                for (index, bundle) in response.bundles.iter().enumerate() {
+                    let path = dir.join(format!("{}.proof", index));
+                    std::fs::write(&path, hex::decode(bundle)?)
+                        .with_context(|| format!("Can't write {:?}", path))?;
+                    println!("Wrote {:?}", path);
+                }
+            }
+        }
+        Command::Block => {
+            println!("{}", serde_json::to_string_pretty(&client.block()?)?);
+        }
+        Command::BlockProof { height, out } => {
+            let response = client.block_proof(BlockProofRequest { height })?;
+            std::fs::write(&out, hex::decode(response.bundle)?)
+                .with_context(|| format!("Can't write {:?}", out))?;
+            println!("Wrote {:?}", out);
+        }
+        Command::Keys(command) => keys::run(&client, command)?,
+        Command::Genesis { .. } | Command::Snapshot(_) | Command::Prove { .. } => unreachable!(),
+    }
+
+    Ok(())
+}
+
+fn encode_args(args: &CallArgs, method: Option<&MethodAbi>) -> Result<Vec<u8>> {
+    Ok(match (&args.args, &args.args_hex) {
+        (Some(json), _) => {
+            let value: Value = serde_json::from_str(json).context("Invalid JSON arguments")?;
+            method
+                .ok_or_else(|| anyhow!("ABI is required for JSON arguments"))?
+                .encode_args(&value)?
+        }
+        (None, Some(hex_args)) => hex::decode(hex_args.trim_start_matches("0x"))?,
+        (None, None) => Vec::new(),
+    })
+}
+
+fn call(client: &Client, args: CallArgs, view: bool) -> Result<()> {
+    let abi = args.abi.as_deref().map(ContractAbi::load).transpose()?;
+    let method = abi
+        .as_ref()
+        .map(|abi| abi.method(&args.method))
+        .transpose()?;
+
+    let encoded_args = encode_args(&args, method)?;
+
+    let (transaction, outcome) = if view {
+        let outcome = client.view(CallRequest {
+            signer: args.signer,
+            contract: args.contract,
+            method: args.method,
+            args: encoded_args,
+            attached_gas: args.gas,
+            block_height: args.height,
+        })?;
+        (None, outcome)
+    } else {
+        if args.height.is_some() {
+            bail!("--height only applies to view calls");
+        }
+
+        let secret_key = args
+            .secret_key
+            .as_ref()
+            .ok_or_else(|| anyhow!("--secret-key is required to sign the call"))?;
+        let call = ContractCall {
+            account: args.contract,
+            function_call: FunctionCall {
+                method: args.method,
+                args: encoded_args,
+            },
+            attached_gas: args.gas,
+        };
+        let transaction = keys::sign(
+            client,
+            &args.signer,
+            secret_key,
+            vec![Action::FunctionCall(call)],
+        )?;
+        let mut outcome = client.send_transaction(transaction)?;
+        let call_outcome = outcome
+            .actions
+            .pop()
+            .expect("Expected an outcome of the call");
+        (Some(outcome.hash), call_outcome)
+    };
+
+    let output = match (&outcome.output, method) {
+        (Some(output), Some(method)) => method
+            .decode_output(&hex::decode(output)?)?
+            .unwrap_or_else(|| Value::from(output.clone())),
+        (Some(output), None) => Value::from(output.clone()),
+        (None, _) => Value::Null,
+    };
+
+    let printed = json!({
+        "transaction": transaction,
+        "output": output,
+        "cross_calls_hashes": outcome.cross_calls_hashes,
+        "gas_used": outcome.gas_used,
+        "receipts": outcome.receipts,
+        "scheduled_calls": outcome.scheduled_calls,
+    });
+    println!("{}", serde_json::to_string_pretty(&printed)?);
+
+    Ok(())
+}
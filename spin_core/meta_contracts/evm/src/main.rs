@@ -6,6 +6,7 @@ use evm::executor::stack::{MemoryStackState, StackExecutor, StackSubstateMetadat
 use evm::{backend::ApplyBackend, Config};
 use memory_backend::{EvmBasic, EvmMemoryBackend};
 use primitive_types::{H160, H256, U256};
+use spin_sdk::spin_primitives::evm::EvmAccount;
 use std::collections::BTreeMap;
 
 mod memory_backend;
@@ -14,17 +15,28 @@ struct Contract;
 
 #[spin_sdk_macros::contract]
 impl Contract {
-    pub fn init() {
-        for user in ["alice", "bob", "charlie", "eve"] {
-            let address = env::get_evm_address(AccountId::new(format!("{}.spin", user)));
+    pub fn init(accounts: Vec<EvmAccount>) {
+        if env::caller() != env::contract() {
+            panic!("EVM accounts can only be initialized at genesis");
+        }
+
+        for account in accounts {
+            let address = H160::from(account.address);
             let basic = EvmBasic {
-                balance: U256::from(10_000_000_000_000_000_000_000_000u128).into(),
-                nonce: U256::one().into(),
+                balance: account.balance,
+                nonce: account.nonce,
             };
 
-            let storage = BTreeMap::<H256, H256>::new();
+            let storage = account
+                .storage
+                .into_iter()
+                .map(|(index, value)| (H256::from(index), H256::from(value)))
+                .collect::<BTreeMap<H256, H256>>();
 
             env::set_state(format!("basic_{:?}", address), basic);
+            if !account.code.is_empty() {
+                env::set_state(format!("code_{:?}", address), account.code);
+            }
             env::set_state(
                 format!("storage_{:?}", address),
                 bincode::serialize(&storage).unwrap(),
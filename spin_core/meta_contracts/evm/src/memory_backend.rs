@@ -118,6 +118,12 @@ impl<'vicinity> Backend for EvmMemoryBackend<'vicinity> {
     }
 }
 
+fn delete_account(address: H160) {
+    env::remove_state(format!("basic_{:?}", address));
+    env::remove_state(format!("code_{:?}", address));
+    env::remove_state(format!("storage_{:?}", address));
+}
+
 impl<'vicinity> ApplyBackend for EvmMemoryBackend<'vicinity> {
     fn apply<A, I, L>(&mut self, values: A, logs: L, delete_empty: bool)
     where
@@ -135,6 +141,7 @@ impl<'vicinity> ApplyBackend for EvmMemoryBackend<'vicinity> {
                     reset_storage,
                 } => {
                     let is_empty = {
+                        let is_basic_empty = basic.balance.is_zero() && basic.nonce.is_zero();
                         env::set_state(format!("basic_{:?}", address), EvmBasic::from(basic));
 
                         if let Some(code) = code {
@@ -177,24 +184,14 @@ impl<'vicinity> ApplyBackend for EvmMemoryBackend<'vicinity> {
                             bincode::serialize(&storage).unwrap(),
                         );
 
-                        // TODO
-                        // account.balance == U256::zero()
-                        //     && account.nonce == U256::zero()
-                        //     && account.code.is_empty()
-                        false
+                        is_basic_empty && self.code(address).is_empty()
                     };
 
                     if is_empty && delete_empty {
-                        // TODO
-                        // self.state.remove(&address);
-                        unimplemented!("delete_empty");
+                        delete_account(address);
                     }
                 }
-                Apply::Delete { address: _ } => {
-                    // TODO
-                    // self.state.remove(&address);
-                    unimplemented!("delete");
-                }
+                Apply::Delete { address } => delete_account(address),
             }
         }
 
@@ -0,0 +1,35 @@
+use anyhow::Result;
+use clap::Parser;
+
+use spin_node::remote_prover::{self, DEFAULT_PROVER_ADDR};
+
+#[derive(Parser)]
+#[command(about = "Local stand-in for a Spin proving service")]
+struct Args {
+    /// Address to serve the proving protocol on
+    #[arg(long, default_value = DEFAULT_PROVER_ADDR)]
+    addr: String,
+
+    /// Jobs proven at the same time
+    #[arg(long, default_value_t = 1)]
+    workers: usize,
+}
+
+fn install_tracing() {
+    use tracing_subscriber::{fmt, prelude::*, registry, EnvFilter};
+
+    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "warn,spin_node=debug".to_owned());
+
+    let main_layer = fmt::layer()
+        .event_format(fmt::format().with_ansi(true))
+        .with_filter(EnvFilter::from(filter));
+
+    registry().with(main_layer).init();
+}
+
+fn main() -> Result<()> {
+    install_tracing();
+
+    let args = Args::parse();
+    remote_prover::serve(&args.addr, args.workers)
+}
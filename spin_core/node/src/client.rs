@@ -0,0 +1,83 @@
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+use spin_primitives::{AccessKey, Account, BlockInfo, SignedTransaction};
+
+use crate::{
+    proving::TransactionRecord,
+    rpc::{
+        AccessKeyRequest, AccountRequest, BlockProofRequest, BlockProofResponse, CallOutcome,
+        CallRequest, RpcRequest, RpcResponse, StorageEntry, StorageProofRequest,
+        StorageProofResponse, StorageRequest, TransactionOutcome, TransactionProofsResponse,
+        TransactionRequest,
+    },
+};
+
+/// JSON-RPC client of a running node.
+pub struct RpcClient {
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    fn request<P: Serialize, R: DeserializeOwned>(&self, method: &str, params: P) -> Result<R> {
+        let request = RpcRequest::new(method, serde_json::to_value(params)?);
+
+        let response: RpcResponse = ureq::post(&self.url).send_json(&request)?.into_json()?;
+
+        if let Some(error) = response.error {
+            return Err(anyhow!("RPC error {}: {}", error.code, error.message));
+        }
+
+        let result = response
+            .result
+            .ok_or_else(|| anyhow!("RPC response has neither result nor error"))?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    pub fn send_transaction(&self, transaction: SignedTransaction) -> Result<TransactionOutcome> {
+        self.request("send_transaction", transaction)
+    }
+
+    pub fn view(&self, request: CallRequest) -> Result<CallOutcome> {
+        self.request("view", request)
+    }
+
+    pub fn storage(&self, request: StorageRequest) -> Result<Vec<StorageEntry>> {
+        self.request("storage", request)
+    }
+
+    pub fn storage_proof(&self, request: StorageProofRequest) -> Result<StorageProofResponse> {
+        self.request("storage_proof", request)
+    }
+
+    pub fn account(&self, request: AccountRequest) -> Result<Option<Account>> {
+        self.request("account", request)
+    }
+
+    pub fn block(&self) -> Result<BlockInfo> {
+        self.request("block", ())
+    }
+
+    pub fn transaction(&self, request: TransactionRequest) -> Result<Option<TransactionRecord>> {
+        self.request("transaction", request)
+    }
+
+    pub fn transaction_proofs(
+        &self,
+        request: TransactionRequest,
+    ) -> Result<TransactionProofsResponse> {
+        self.request("transaction_proofs", request)
+    }
+
+    pub fn block_proof(&self, request: BlockProofRequest) -> Result<BlockProofResponse> {
+        self.request("block_proof", request)
+    }
+
+    pub fn access_key(&self, request: AccessKeyRequest) -> Result<Option<AccessKey>> {
+        self.request("access_key", request)
+    }
+}
@@ -0,0 +1,663 @@
+use anyhow::{anyhow, bail, Context, Result};
+use borsh::BorshSerialize;
+use spin_primitives::{
+    AccessKey, Account, AccountId, Action, BlockInfo, ContractCall, ExecutionCommittment,
+    FunctionCall, PromiseResult, SignedTransaction, Transaction,
+};
+use spin_runtime::{
+    config::RuntimeConfig,
+    context::ExecutionContext,
+    executor,
+    genesis::{ChainConfig, Genesis},
+    prover::{FakeProver, LocalProver, Prover},
+    state::{
+        merkle::Hash,
+        pruning::{PruningMode, PruningStats},
+        receipts::Receipt,
+        schedule::ScheduledCall,
+        snapshot::Snapshot,
+        State,
+    },
+    transaction,
+};
+use spin_verifier::{BlockProof, ProofBundle};
+use tracing::{debug, warn};
+
+use std::{
+    collections::BTreeSet,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use proving::{ProvingJob, ProvingQueue, ProvingStatus, TransactionRecord};
+use rpc::{
+    AccessKeyRequest, AccountRequest, BlockProofRequest, BlockProofResponse, CallOutcome,
+    CallRequest, StorageEntry, StorageProofRequest, StorageProofResponse, StorageRequest,
+    TransactionOutcome, TransactionProofsResponse, TransactionRequest,
+};
+
+pub mod client;
+pub mod proving;
+pub mod remote_prover;
+pub mod rpc;
+
+/// Receipts executed right after a transaction, the rest waits for the next one or an idle node.
+pub const MAX_RECEIPTS_PER_TRANSACTION: usize = 16;
+
+/// Scheduled calls executed at the start of a block, the rest runs in the next blocks.
+pub const MAX_SCHEDULED_CALLS_PER_BLOCK: usize = 64;
+
+/// Executes requests against the local state.
+pub struct Node {
+    config: Arc<RuntimeConfig>,
+    state: Arc<RwLock<State>>,
+    proving: ProvingQueue,
+    prover: Arc<dyn Prover>,
+}
+
+impl Node {
+    /// Opens the state of the configured backend.
+    pub fn open(config: RuntimeConfig) -> Result<Self> {
+        let state = config.state.open()?;
+        let proving = ProvingQueue::open(&state)?;
+
+        Ok(Self {
+            config: Arc::new(config),
+            state: Arc::new(RwLock::new(state)),
+            proving,
+            prover: Arc::new(LocalProver),
+        })
+    }
+
+    /// Proves calls and transactions with the prover instead of in process.
+    pub fn with_prover(mut self, prover: Arc<dyn Prover>) -> Self {
+        self.prover = prover;
+        self
+    }
+
+    /// Makes fake receipts instead of proving, which fails on a production chain.
+    pub fn with_dev_mode(self) -> Result<Self> {
+        if self.chain_config()?.map_or(false, |chain| chain.production) {
+            bail!("Dev mode can't be enabled on a production chain");
+        }
+        warn!("Dev mode is enabled, receipts are fake");
+        Ok(self.with_prover(Arc::new(FakeProver)))
+    }
+
+    /// Starts the worker threads proving executed transactions in the background.
+    pub fn start_provers(&self, workers: usize) -> Result<()> {
+        self.proving.spawn_workers(
+            self.config.clone(),
+            self.state.clone(),
+            self.prover.clone(),
+            workers,
+        )
+    }
+
+    /// Builds the genesis state from the file and returns its root.
+    pub fn init_genesis(&self, genesis_path: &Path) -> Result<Hash> {
+        let genesis = Genesis::load(genesis_path)?;
+        let base_dir = genesis_path.parent().unwrap_or_else(|| Path::new("."));
+        genesis.build(base_dir, &self.config, self.state.clone())
+    }
+
+    /// Loads a snapshot file into the empty state and returns its root.
+    pub fn import_snapshot(&self, path: &Path) -> Result<Hash> {
+        self.state.write().unwrap().import_snapshot(path)
+    }
+
+    /// Writes the state at the end of the block, or the latest state, to a snapshot file.
+    pub fn export_snapshot(&self, path: &Path, height: Option<u64>) -> Result<Snapshot> {
+        let state = self.state.read().unwrap();
+        let root = match height {
+            Some(height) => state
+                .root_at(height)?
+                .ok_or_else(|| anyhow!("State of block {} not found", height))?,
+            None => state.root(),
+        };
+        state.export_snapshot(root, path)
+    }
+
+    pub fn state_root(&self) -> Hash {
+        self.state.read().unwrap().root()
+    }
+
+    /// Verifies and executes the actions of the transaction in order.
+    ///
+    /// State changes are committed only if all actions succeeded, while the nonce and allowance
+    /// of the access key are updated either way. A successful transaction is queued for proving.
+    pub fn send_transaction(&self, signed: SignedTransaction) -> Result<TransactionOutcome> {
+        let key = transaction::verify(&self.state.read().unwrap(), &signed)?;
+        let tx = signed.transaction;
+        let hash = tx.hash();
+        let (height, root) = {
+            let state = self.state.read().unwrap();
+            (state.block()?.height, state.root())
+        };
+
+        let outcome = self.execute_actions(&tx);
+
+        let mut state = self.state.write().unwrap();
+        let gas_used = match &outcome {
+            Ok(outcome) => outcome.gas_used,
+            // Verified not to overflow.
+            Err(_) => tx.attached_gas().unwrap_or(u64::MAX),
+        };
+        if outcome.is_err() {
+            state.rollback();
+        }
+        transaction::consume_access_key(&mut state, &tx, key, gas_used)?;
+        state.commit()?;
+        drop(state);
+
+        if let Ok(outcome) = &outcome {
+            self.proving.push(
+                hash,
+                ProvingJob::Transaction(tx),
+                height,
+                root,
+                outcome.clone(),
+            )?;
+        }
+
+        self.process_receipts(MAX_RECEIPTS_PER_TRANSACTION)?;
+
+        outcome
+    }
+
+    fn execute_actions(&self, tx: &Transaction) -> Result<TransactionOutcome> {
+        let mut created = BTreeSet::new();
+        let mut outcomes = Vec::with_capacity(tx.actions.len());
+
+        for (index, action) in tx.actions.iter().enumerate() {
+            let outcome = match action {
+                Action::FunctionCall(call) => self
+                    .execute(tx.signer.clone(), tx.signer.clone(), call.clone())
+                    .map(CallOutcome::from),
+                action => transaction::apply_action(
+                    &mut self.state.write().unwrap(),
+                    &tx.signer,
+                    action,
+                    &mut created,
+                )
+                .map(|_| CallOutcome::default()),
+            };
+            outcomes.push(outcome.with_context(|| format!("Action {} failed", index))?);
+        }
+
+        Ok(TransactionOutcome {
+            hash: hex::encode(tx.hash()),
+            gas_used: outcomes.iter().map(|outcome| outcome.gas_used).sum(),
+            actions: outcomes,
+        })
+    }
+
+    pub fn chain_config(&self) -> Result<Option<ChainConfig>> {
+        self.state.read().unwrap().chain_config()
+    }
+
+    pub fn block(&self) -> Result<BlockInfo> {
+        self.state.read().unwrap().block()
+    }
+
+    /// Starts the next block and executes the calls scheduled for it, before any transaction of
+    /// the block.
+    ///
+    /// Every scheduled call is a separate execution committed on its own; a recurring call is
+    /// rescheduled whether it succeeded or not.
+    pub fn produce_block(&self, timestamp: u64) -> Result<BlockInfo> {
+        let mut state = self.state.write().unwrap();
+        let previous = state.block()?;
+        let block = BlockInfo {
+            height: previous.height + 1,
+            timestamp: timestamp.max(previous.timestamp),
+        };
+        state.set_block(&block)?;
+        state.commit()?;
+
+        let due = state.due_scheduled_calls(&block)?;
+        drop(state);
+
+        for (id, scheduled) in due.into_iter().take(MAX_SCHEDULED_CALLS_PER_BLOCK) {
+            debug!(
+                id,
+                height = block.height,
+                contract = ?scheduled.call.account,
+                "Executing scheduled call"
+            );
+
+            let root = self.state.read().unwrap().root();
+            let execution = self.execute(
+                scheduled.signer.clone(),
+                scheduled.caller.clone(),
+                scheduled.call.clone(),
+            );
+
+            let mut state = self.state.write().unwrap();
+            let outcome = match execution {
+                Ok(execution) => Some(CallOutcome::from(execution)),
+                Err(e) => {
+                    warn!(id, error = ?e, "Scheduled call failed");
+                    state.rollback();
+                    None
+                }
+            };
+            match scheduled.schedule.next() {
+                Some(schedule) => state.set_scheduled_call(
+                    id,
+                    &ScheduledCall {
+                        schedule,
+                        ..scheduled.clone()
+                    },
+                )?,
+                None => state.remove_scheduled_call(id),
+            }
+            state.commit()?;
+            drop(state);
+
+            if let Some(outcome) = outcome {
+                self.queue_call(
+                    block.height,
+                    root,
+                    scheduled.signer,
+                    scheduled.caller,
+                    scheduled.call,
+                    outcome,
+                )?;
+            }
+        }
+
+        Ok(block)
+    }
+
+    /// Deletes the state of the blocks the pruning mode doesn't retain, except the states that
+    /// queued and in-progress proving jobs replay their executions on.
+    pub fn prune(&self, mode: PruningMode) -> Result<PruningStats> {
+        let mut state = self.state.write().unwrap();
+        let pending_roots = self.proving.pending_roots()?;
+        state.prune(mode, &pending_roots)
+    }
+
+    /// Executes the call and drops its state changes, against the state at the end of the
+    /// requested block or the latest state.
+    pub fn view(&self, request: CallRequest) -> Result<CallOutcome> {
+        let state = self.view_state(request.block_height)?;
+
+        let outcome = Self::execute_on(
+            &self.config,
+            &state,
+            request.signer.clone(),
+            request.signer,
+            ContractCall {
+                account: request.contract,
+                function_call: FunctionCall {
+                    method: request.method,
+                    args: request.args,
+                },
+                attached_gas: request.attached_gas,
+            },
+        );
+        state.write().unwrap().rollback();
+        let outcome = outcome.map(CallOutcome::from);
+        outcome
+    }
+
+    /// Executes the call like [`Node::view`] and proves it with all of its cross-contract calls.
+    pub fn prove(&self, request: CallRequest) -> Result<ProofBundle> {
+        let state = self.view_state(request.block_height)?;
+        let call = Self::execute_on(
+            &self.config,
+            &state,
+            request.signer.clone(),
+            request.signer,
+            ContractCall {
+                account: request.contract,
+                function_call: FunctionCall {
+                    method: request.method,
+                    args: request.args,
+                },
+                attached_gas: request.attached_gas,
+            },
+        )
+        .and_then(|execution| {
+            executor::prove(&execution.context.read().unwrap(), self.prover.as_ref())
+        });
+        state.write().unwrap().rollback();
+
+        Ok(ProofBundle { call: call? })
+    }
+
+    fn view_state(&self, block_height: Option<u64>) -> Result<Arc<RwLock<State>>> {
+        Ok(match block_height {
+            Some(height) => Arc::new(RwLock::new(self.state.read().unwrap().at_height(height)?)),
+            None => self.state.clone(),
+        })
+    }
+
+    pub fn storage(&self, request: StorageRequest) -> Result<Vec<StorageEntry>> {
+        let state = self.state.read().unwrap();
+        let entries = match request.block_height {
+            Some(height) => state
+                .at_height(height)?
+                .storage_entries(&request.contract)?,
+            None => state.storage_entries(&request.contract)?,
+        };
+
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| StorageEntry { key, value })
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Returns the storage value with its Merkle proof against a committed state root.
+    pub fn storage_proof(&self, request: StorageProofRequest) -> Result<StorageProofResponse> {
+        let root = request
+            .root
+            .map(|root| -> Result<Hash> {
+                hex::decode(root.trim_start_matches("0x"))?
+                    .try_into()
+                    .map_err(|_| anyhow!("State root must be 32 bytes"))
+            })
+            .transpose()?;
+
+        let state = self.state.read().unwrap();
+        let root = root.unwrap_or_else(|| state.root());
+        let (value, proof) = state.storage_proof(Some(root), &request.contract, &request.key)?;
+
+        Ok(StorageProofResponse {
+            root: hex::encode(root),
+            value: value.map(hex::encode),
+            proof: hex::encode(proof.into_bytes()),
+        })
+    }
+
+    pub fn transaction(&self, request: TransactionRequest) -> Result<Option<TransactionRecord>> {
+        self.proving.transaction(&request.decoded_hash()?)
+    }
+
+    /// Returns the proof bundles of the function calls of a proven transaction, in order.
+    pub fn transaction_proofs(
+        &self,
+        request: TransactionRequest,
+    ) -> Result<TransactionProofsResponse> {
+        let bundles = self
+            .proving
+            .proofs(&request.decoded_hash()?)?
+            .ok_or_else(|| anyhow!("Transaction {} is not proven", request.hash))?;
+
+        Ok(TransactionProofsResponse {
+            bundles: bundles.into_iter().map(hex::encode).collect(),
+        })
+    }
+
+    /// Returns the proof bundles of every function call executed in a finished block, with the
+    /// state roots before and after it, once all of its executions are proven.
+    pub fn block_proof(&self, request: BlockProofRequest) -> Result<BlockProofResponse> {
+        let height = request.height;
+        let (previous_root, root) = {
+            let state = self.state.read().unwrap();
+            if height >= state.block()?.height {
+                bail!("Block {} is not finished", height);
+            }
+            let previous = height
+                .checked_sub(1)
+                .ok_or_else(|| anyhow!("Genesis block has no proof"))?;
+            let root_at = |height| {
+                state
+                    .root_at(height)?
+                    .ok_or_else(|| anyhow!("State root of block {} not found", height))
+            };
+            (root_at(previous)?, root_at(height)?)
+        };
+
+        let mut bundles = Vec::new();
+        for (hash, record) in self.proving.block_jobs(height)? {
+            if record.proving != ProvingStatus::Proven {
+                bail!(
+                    "Transaction {} of block {} is not proven",
+                    hex::encode(hash),
+                    height
+                );
+            }
+            let proofs = self
+                .proving
+                .proofs(&hash)?
+                .ok_or_else(|| anyhow!("Proofs of transaction {} not found", hex::encode(hash)))?;
+            for bundle in proofs {
+                bundles.push(ProofBundle::from_bytes(&bundle)?);
+            }
+        }
+
+        let proof = BlockProof {
+            height,
+            previous_root,
+            root,
+            bundles,
+        };
+        Ok(BlockProofResponse {
+            bundle: hex::encode(proof.to_bytes()?),
+        })
+    }
+
+    pub fn account(&self, request: AccountRequest) -> Result<Option<Account>> {
+        let state = self.state.read().unwrap();
+        match request.block_height {
+            Some(height) => state.at_height(height)?.get_account(&request.account),
+            None => state.get_account(&request.account),
+        }
+    }
+
+    pub fn access_key(&self, request: AccessKeyRequest) -> Result<Option<AccessKey>> {
+        self.state
+            .read()
+            .unwrap()
+            .get_access_key(&request.account, &request.public_key)
+    }
+
+    /// Executes receipts of asynchronous calls in the order they were scheduled, at most `limit`
+    /// of them, and returns how many were executed.
+    ///
+    /// Every receipt is a separate execution committed on its own; its callback, if any, is
+    /// scheduled with the result and executed by a later call.
+    pub fn process_receipts(&self, limit: usize) -> Result<usize> {
+        for processed in 0..limit {
+            let (id, receipt) = match self.state.read().unwrap().next_receipt()? {
+                Some(next) => next,
+                None => return Ok(processed),
+            };
+            debug!(id, contract = ?receipt.call.account, "Executing receipt");
+
+            let (height, root) = {
+                let state = self.state.read().unwrap();
+                (state.block()?.height, state.root())
+            };
+            let execution = self.execute(
+                receipt.signer.clone(),
+                receipt.caller.clone(),
+                receipt.call.clone(),
+            );
+
+            let mut state = self.state.write().unwrap();
+            let (result, outcome) = match execution {
+                Ok(execution) => (
+                    PromiseResult::Successful(
+                        execution
+                            .committment
+                            .as_ref()
+                            .map(|committment| committment.output.clone())
+                            .unwrap_or_default(),
+                    ),
+                    Some(CallOutcome::from(execution)),
+                ),
+                Err(e) => {
+                    warn!(id, error = ?e, "Receipt failed");
+                    state.rollback();
+                    (PromiseResult::Failed(format!("{:#}", e)), None)
+                }
+            };
+
+            state.remove_receipt(id);
+            if let Some(callback) = receipt.callback {
+                let mut args = result.try_to_vec()?;
+                args.extend(callback.args);
+
+                state.push_receipt(&Receipt {
+                    signer: receipt.signer.clone(),
+                    caller: receipt.caller.clone(),
+                    call: ContractCall {
+                        account: receipt.caller.clone(),
+                        function_call: FunctionCall {
+                            method: callback.method,
+                            args,
+                        },
+                        attached_gas: callback.attached_gas,
+                    },
+                    callback: None,
+                })?;
+            }
+            state.commit()?;
+            drop(state);
+
+            if let Some(outcome) = outcome {
+                self.queue_call(
+                    height,
+                    root,
+                    receipt.signer,
+                    receipt.caller,
+                    receipt.call,
+                    outcome,
+                )?;
+            }
+        }
+
+        Ok(limit)
+    }
+
+    /// Records a call the node executed on its own in the block, on the state with the root, and
+    /// queues it for proving.
+    fn queue_call(
+        &self,
+        height: u64,
+        root: Hash,
+        signer: AccountId,
+        caller: AccountId,
+        call: ContractCall,
+        outcome: CallOutcome,
+    ) -> Result<()> {
+        let job = ProvingJob::Call {
+            signer,
+            caller,
+            call,
+        };
+        let hash = job.hash(&root)?;
+        debug!(hash = hex::encode(hash), "Call queued for proving");
+
+        let outcome = TransactionOutcome {
+            hash: hex::encode(hash),
+            gas_used: outcome.gas_used,
+            actions: vec![outcome],
+        };
+        self.proving.push(hash, job, height, root, outcome)
+    }
+
+    fn execute(
+        &self,
+        signer: AccountId,
+        caller: AccountId,
+        call: ContractCall,
+    ) -> Result<Execution> {
+        Self::execute_on(&self.config, &self.state, signer, caller, call)
+    }
+
+    fn execute_on(
+        config: &Arc<RuntimeConfig>,
+        state: &Arc<RwLock<State>>,
+        signer: AccountId,
+        caller: AccountId,
+        call: ContractCall,
+    ) -> Result<Execution> {
+        debug!(
+            ?signer,
+            contract = ?call.account,
+            method = call.function_call.method,
+            "Executing call"
+        );
+
+        let ctx = Arc::new(RwLock::new(ExecutionContext::new(
+            config.clone(),
+            state.clone(),
+            signer,
+            caller,
+            call.account,
+            call.attached_gas,
+            call.function_call,
+        )));
+
+        let session = executor::execute(ctx.clone())?;
+        let journal = session.journal.clone();
+        ctx.write().unwrap().set_execution_session(session);
+        let context = ctx.clone();
+        let ctx = ctx.read().unwrap();
+
+        let receipts = ctx
+            .receipts()
+            .iter()
+            .map(|receipt| state.write().unwrap().push_receipt(receipt))
+            .collect::<Result<_>>()?;
+        let scheduled_calls = ctx
+            .scheduled_calls()
+            .iter()
+            .map(|scheduled| state.write().unwrap().push_scheduled_call(scheduled))
+            .collect::<Result<_>>()?;
+
+        let committment = if journal.is_empty() {
+            None
+        } else {
+            Some(ExecutionCommittment::try_from_bytes(journal)?)
+        };
+
+        Ok(Execution {
+            committment,
+            gas_used: ctx.total_used_gas(),
+            receipts,
+            scheduled_calls,
+            context,
+        })
+    }
+}
+
+struct Execution {
+    committment: Option<ExecutionCommittment>,
+    gas_used: u64,
+    /// Ids of the scheduled receipts.
+    receipts: Vec<u64>,
+    scheduled_calls: Vec<u64>,
+    /// Context of the call with the sessions of it and its cross-contract calls, to prove it.
+    context: Arc<RwLock<ExecutionContext>>,
+}
+
+impl From<Execution> for CallOutcome {
+    fn from(execution: Execution) -> Self {
+        let (output, cross_calls_hashes) = match execution.committment {
+            Some(committment) => (
+                Some(hex::encode(committment.output)),
+                committment
+                    .cross_calls_hashes
+                    .iter()
+                    .map(hex::encode)
+                    .collect(),
+            ),
+            None => (None, Vec::new()),
+        };
+
+        CallOutcome {
+            output,
+            cross_calls_hashes,
+            gas_used: execution.gas_used,
+            receipts: execution.receipts,
+            scheduled_calls: execution.scheduled_calls,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::{anyhow, Result};
+use clap::{Parser, ValueEnum};
+use std::{
+    io::Read,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, info, warn};
+
+use spin_node::{remote_prover::RemoteProver, rpc, Node, MAX_RECEIPTS_PER_TRANSACTION};
+use spin_runtime::{
+    config::RuntimeConfig,
+    state::{merkle::EMPTY_HASH, pruning::PruningMode},
+};
+use spin_verifier::DEV_MODE_ENV;
+
+const RECEIPTS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Block time of a state built without a genesis.
+const DEFAULT_BLOCK_TIME_MS: u64 = 1000;
+/// Blocks between two runs of pruning.
+const PRUNING_INTERVAL_BLOCKS: u64 = 100;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Pruning {
+    /// Keep the state of every block
+    Archive,
+    /// Delete the state of old blocks except checkpoints
+    Prune,
+}
+
+#[derive(Parser)]
+#[command(about = "Spin node with a JSON-RPC interface")]
+struct Args {
+    /// Runtime config file, the default config if not set
+    #[arg(long)]
+    config: Option<std::path::PathBuf>,
+
+    /// State directory, overriding the state backend of the config
+    #[arg(long)]
+    state: Option<std::path::PathBuf>,
+
+    /// Genesis file used to initialize an empty state
+    #[arg(long, conflicts_with = "snapshot")]
+    genesis: Option<std::path::PathBuf>,
+
+    /// Snapshot file used to initialize an empty state
+    #[arg(long)]
+    snapshot: Option<std::path::PathBuf>,
+
+    /// Address to serve JSON-RPC on
+    #[arg(long, default_value = rpc::DEFAULT_RPC_ADDR)]
+    rpc_addr: String,
+
+    /// Milliseconds between blocks, the block time of the chain config by default
+    #[arg(long)]
+    block_time: Option<u64>,
+
+    /// Whether the state of old blocks is kept
+    #[arg(long, value_enum, default_value_t = Pruning::Archive)]
+    pruning: Pruning,
+
+    /// Number of latest blocks whose state is kept when pruning
+    #[arg(long, default_value_t = 1000)]
+    keep_blocks: u64,
+
+    /// Blocks between checkpoints whose state is kept when pruning, 0 for none
+    #[arg(long, default_value_t = 10000)]
+    checkpoint_interval: u64,
+
+    /// Worker threads proving executed transactions, none by default since proving is slow
+    #[arg(long, default_value_t = 0)]
+    provers: usize,
+
+    /// URL of a proving service the provers send transactions to, e.g. http://127.0.0.1:3040.
+    /// Without it, they prove in process.
+    #[arg(long)]
+    prover_url: Option<String>,
+
+    /// Make fake receipts instead of proving, for tests; refused on a production chain
+    #[arg(long, env = DEV_MODE_ENV, conflicts_with = "prover_url")]
+    dev_mode: bool,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Expected time after the Unix epoch")
+        .as_millis() as u64
+}
+
+fn install_tracing() {
+    use tracing_subscriber::{fmt, prelude::*, registry, EnvFilter};
+
+    let filter = std::env::var("RUST_LOG")
+        .unwrap_or_else(|_| "warn,spin_runtime,spin_node=debug".to_owned());
+
+    let main_layer = fmt::layer()
+        .event_format(fmt::format().with_ansi(true))
+        .with_filter(EnvFilter::from(filter));
+
+    registry().with(main_layer).init();
+}
+
+fn main() -> Result<()> {
+    install_tracing();
+
+    let args = Args::parse();
+    let config = RuntimeConfig::resolve(args.config.as_deref(), args.state.as_deref())?;
+    let mut node = Node::open(config)?;
+
+    if let Some(genesis) = args.genesis {
+        if node.state_root() == EMPTY_HASH {
+            node.init_genesis(&genesis)?;
+        } else {
+            info!("State is already initialized, genesis is ignored");
+        }
+    }
+    if let Some(snapshot) = args.snapshot {
+        if node.state_root() == EMPTY_HASH {
+            node.import_snapshot(&snapshot)?;
+        } else {
+            info!("State is already initialized, snapshot is ignored");
+        }
+    }
+    info!(root = hex::encode(node.state_root()), "State loaded");
+
+    if let Some(url) = &args.prover_url {
+        node = node.with_prover(Arc::new(RemoteProver::new(url.clone())));
+    }
+    if args.dev_mode {
+        node = node.with_dev_mode()?;
+    }
+    node.start_provers(args.provers)?;
+
+    let server = tiny_http::Server::http(&args.rpc_addr)
+        .map_err(|e| anyhow!("Can't start RPC server on {}: {}", args.rpc_addr, e))?;
+    info!(addr = args.rpc_addr, "RPC server started");
+
+    let block_time = match args.block_time {
+        Some(block_time) => block_time,
+        None => node
+            .chain_config()?
+            .map_or(DEFAULT_BLOCK_TIME_MS, |chain| chain.block_time_ms),
+    };
+    let block_time = Duration::from_millis(block_time);
+    let pruning = match args.pruning {
+        Pruning::Archive => PruningMode::Archive,
+        Pruning::Prune => PruningMode::Prune {
+            keep_blocks: args.keep_blocks,
+            checkpoint_interval: args.checkpoint_interval,
+        },
+    };
+    let mut next_block = Instant::now();
+
+    loop {
+        if Instant::now() >= next_block {
+            // Calls scheduled for the block run before the transactions received during it.
+            match node.produce_block(now_millis()) {
+                Ok(block) => {
+                    debug!(height = block.height, "Block produced");
+                    if block.height % PRUNING_INTERVAL_BLOCKS == 0 {
+                        if let Err(e) = node.prune(pruning) {
+                            warn!(error = ?e, "Can't prune state");
+                        }
+                    }
+                }
+                Err(e) => warn!(error = ?e, "Can't produce block"),
+            }
+            next_block = Instant::now() + block_time;
+        }
+
+        let timeout =
+            RECEIPTS_POLL_INTERVAL.min(next_block.saturating_duration_since(Instant::now()));
+        let mut request = match server.recv_timeout(timeout)? {
+            Some(request) => request,
+            None => {
+                // Idle, run the asynchronous calls left over by previous transactions.
+                if let Err(e) = node.process_receipts(MAX_RECEIPTS_PER_TRANSACTION) {
+                    warn!(error = ?e, "Can't process receipts");
+                }
+                continue;
+            }
+        };
+
+        let mut body = String::new();
+        if let Err(e) = request.as_reader().read_to_string(&mut body) {
+            warn!(error = ?e, "Can't read RPC request");
+            continue;
+        }
+
+        let response = tiny_http::Response::from_string(rpc::handle(&node, &body)).with_header(
+            tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+        );
+
+        if let Err(e) = request.respond(response) {
+            warn!(error = ?e, "Can't send RPC response");
+        }
+    }
+}
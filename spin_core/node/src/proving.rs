@@ -0,0 +1,366 @@
+use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use spin_primitives::{AccountId, Action, ContractCall, Transaction};
+use spin_runtime::{
+    config::RuntimeConfig,
+    executor,
+    prover::Prover,
+    state::{
+        merkle::{sha256, Hash},
+        State,
+    },
+    transaction,
+};
+use spin_verifier::ProofBundle;
+use tracing::{debug, info, warn};
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::Duration,
+};
+
+use crate::{rpc::TransactionOutcome, Node};
+
+/// Attempts at proving a transaction before its job is given up.
+pub const MAX_PROVING_ATTEMPTS: u32 = 3;
+
+const PROVING_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvingStatus {
+    Queued,
+    Proving,
+    Proven,
+    /// Proving failed on every attempt, with the last error.
+    Failed(String),
+}
+
+/// Execution which a proving job replays.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvingJob {
+    Transaction(Transaction),
+    /// Asynchronous call, callback or scheduled call, which the node executes on its own.
+    Call {
+        signer: AccountId,
+        caller: AccountId,
+        call: ContractCall,
+    },
+}
+
+impl ProvingJob {
+    /// Hash the job is recorded under: the transaction hash, or for a call the hash of the call
+    /// and of the state root it was executed on, which every execution changes.
+    pub fn hash(&self, root: &Hash) -> Result<Hash> {
+        Ok(match self {
+            ProvingJob::Transaction(transaction) => transaction.hash(),
+            ProvingJob::Call {
+                signer,
+                caller,
+                call,
+            } => sha256(&[root.to_vec(), (signer, caller, call).try_to_vec()?].concat()),
+        })
+    }
+}
+
+/// Executed transaction or call with the status of its proof.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionRecord {
+    pub job: ProvingJob,
+    /// Block the execution ran in.
+    #[serde(default)]
+    pub height: u64,
+    /// State root before the execution, which proving replays it on.
+    #[serde(with = "hex")]
+    pub root: Hash,
+    pub outcome: TransactionOutcome,
+    pub proving: ProvingStatus,
+    pub attempts: u32,
+}
+
+/// Executions waiting to be proven by the workers, persisted in the state database.
+#[derive(Clone)]
+pub struct ProvingQueue {
+    /// Transaction records by transaction hash.
+    transactions: sled::Tree,
+    /// Hashes of queued transactions by big-endian job number, in the order they were queued.
+    queue: sled::Tree,
+    /// Proof bundles of the function calls of proven transactions, by transaction hash.
+    proofs: sled::Tree,
+    /// Hashes of the executions of every block by big-endian height and execution number, in
+    /// the order they ran.
+    blocks: sled::Tree,
+    /// Held while the queue is changed, so that no job is claimed twice.
+    lock: Arc<Mutex<()>>,
+}
+
+impl ProvingQueue {
+    /// Opens the queue, putting jobs interrupted by a restart back into it.
+    pub fn open(state: &State) -> Result<Self> {
+        let queue = Self {
+            transactions: state.open_tree("transactions")?,
+            queue: state.open_tree("proving_queue")?,
+            proofs: state.open_tree("proofs")?,
+            blocks: state.open_tree("block_jobs")?,
+            lock: Arc::new(Mutex::new(())),
+        };
+
+        for entry in queue.transactions.iter() {
+            let (hash, record) = entry?;
+            let mut record: TransactionRecord = serde_json::from_slice(&record)?;
+            if record.proving == ProvingStatus::Proving {
+                record.proving = ProvingStatus::Queued;
+                queue.enqueue(hash.as_ref().try_into()?, &record)?;
+            }
+        }
+
+        Ok(queue)
+    }
+
+    /// Records the execution in the block and queues it for proving.
+    pub fn push(
+        &self,
+        hash: Hash,
+        job: ProvingJob,
+        height: u64,
+        root: Hash,
+        outcome: TransactionOutcome,
+    ) -> Result<()> {
+        let record = TransactionRecord {
+            job,
+            height,
+            root,
+            outcome,
+            proving: ProvingStatus::Queued,
+            attempts: 0,
+        };
+        self.enqueue(hash, &record)?;
+
+        let _lock = self.lock.lock().unwrap();
+        let number = match self.blocks.last()? {
+            Some((key, _)) => u64::from_be_bytes(key[8..].try_into()?) + 1,
+            None => 0,
+        };
+        let key = [height.to_be_bytes(), number.to_be_bytes()].concat();
+        self.blocks.insert(key, &hash)?;
+        Ok(())
+    }
+
+    /// Hashes and records of the executions of the block, in the order they ran.
+    pub fn block_jobs(&self, height: u64) -> Result<Vec<(Hash, TransactionRecord)>> {
+        let mut jobs = Vec::new();
+        for entry in self.blocks.scan_prefix(height.to_be_bytes()) {
+            let (_, hash) = entry?;
+            let hash: Hash = hash.as_ref().try_into()?;
+            let record = self
+                .transaction(&hash)?
+                .ok_or_else(|| anyhow!("Transaction {} not found", hex::encode(hash)))?;
+            jobs.push((hash, record));
+        }
+        Ok(jobs)
+    }
+
+    /// State roots of the queued and in-progress jobs, which proving replays them on.
+    pub fn pending_roots(&self) -> Result<Vec<Hash>> {
+        let mut roots = Vec::new();
+        for entry in self.transactions.iter() {
+            let (_, record) = entry?;
+            let record: TransactionRecord = serde_json::from_slice(&record)?;
+            if matches!(
+                record.proving,
+                ProvingStatus::Queued | ProvingStatus::Proving
+            ) {
+                roots.push(record.root);
+            }
+        }
+        Ok(roots)
+    }
+
+    pub fn transaction(&self, hash: &Hash) -> Result<Option<TransactionRecord>> {
+        self.transactions
+            .get(hash)?
+            .map(|record| Ok(serde_json::from_slice(&record)?))
+            .transpose()
+    }
+
+    /// Encoded proof bundles of the function calls of the transaction, in order, `None` if it
+    /// isn't proven.
+    pub fn proofs(&self, hash: &Hash) -> Result<Option<Vec<Vec<u8>>>> {
+        self.proofs
+            .get(hash)?
+            .map(|bundles| Ok(Vec::<Vec<u8>>::try_from_slice(&bundles)?))
+            .transpose()
+    }
+
+    /// Starts the worker threads, which prove queued transactions one at a time.
+    pub fn spawn_workers(
+        &self,
+        config: Arc<RuntimeConfig>,
+        state: Arc<RwLock<State>>,
+        prover: Arc<dyn Prover>,
+        workers: usize,
+    ) -> Result<()> {
+        for worker in 0..workers {
+            let queue = self.clone();
+            let config = config.clone();
+            let state = state.clone();
+            let prover = prover.clone();
+
+            thread::Builder::new()
+                .name(format!("prover-{}", worker))
+                .spawn(move || loop {
+                    match queue.claim() {
+                        Ok(Some((hash, record))) => {
+                            debug!(
+                                worker,
+                                hash = hex::encode(hash),
+                                attempt = record.attempts,
+                                "Proving transaction"
+                            );
+                            let result = prove_job(&config, &state, prover.as_ref(), &record);
+                            if let Err(e) = queue.finish(hash, record, result) {
+                                warn!(worker, error = ?e, "Can't store proving result");
+                            }
+                        }
+                        Ok(None) => thread::sleep(PROVING_POLL_INTERVAL),
+                        Err(e) => {
+                            warn!(worker, error = ?e, "Can't claim proving job");
+                            thread::sleep(PROVING_POLL_INTERVAL);
+                        }
+                    }
+                })?;
+        }
+
+        if workers > 0 {
+            info!(workers, "Provers started");
+        }
+        Ok(())
+    }
+
+    fn set(&self, hash: &Hash, record: &TransactionRecord) -> Result<()> {
+        self.transactions
+            .insert(hash, serde_json::to_vec(record)?)?;
+        Ok(())
+    }
+
+    fn enqueue(&self, hash: Hash, record: &TransactionRecord) -> Result<()> {
+        let _lock = self.lock.lock().unwrap();
+
+        let job = match self.queue.last()? {
+            Some((job, _)) => u64::from_be_bytes(job.as_ref().try_into()?) + 1,
+            None => 0,
+        };
+        self.set(&hash, record)?;
+        self.queue.insert(job.to_be_bytes(), &hash)?;
+        Ok(())
+    }
+
+    /// Takes the oldest queued transaction and marks it as being proven.
+    fn claim(&self) -> Result<Option<(Hash, TransactionRecord)>> {
+        let _lock = self.lock.lock().unwrap();
+
+        let hash: Hash = match self.queue.pop_min()? {
+            Some((_, hash)) => hash.as_ref().try_into()?,
+            None => return Ok(None),
+        };
+        let mut record = self
+            .transaction(&hash)?
+            .ok_or_else(|| anyhow!("Queued transaction {} not found", hex::encode(hash)))?;
+
+        record.proving = ProvingStatus::Proving;
+        record.attempts += 1;
+        self.set(&hash, &record)?;
+
+        Ok(Some((hash, record)))
+    }
+
+    /// Stores the proofs of the transaction, or queues it again if it has attempts left.
+    fn finish(
+        &self,
+        hash: Hash,
+        mut record: TransactionRecord,
+        result: Result<Vec<ProofBundle>>,
+    ) -> Result<()> {
+        match result {
+            Ok(bundles) => {
+                let bundles = bundles
+                    .iter()
+                    .map(ProofBundle::to_bytes)
+                    .collect::<Result<Vec<_>>>()?;
+                self.proofs.insert(hash, bundles.try_to_vec()?)?;
+
+                info!(hash = hex::encode(hash), "Transaction proven");
+                record.proving = ProvingStatus::Proven;
+                self.set(&hash, &record)
+            }
+            Err(e) if record.attempts < MAX_PROVING_ATTEMPTS => {
+                warn!(
+                    hash = hex::encode(hash),
+                    attempt = record.attempts,
+                    error = ?e,
+                    "Proving failed, retrying"
+                );
+                record.proving = ProvingStatus::Queued;
+                self.enqueue(hash, &record)
+            }
+            Err(e) => {
+                warn!(hash = hex::encode(hash), error = ?e, "Proving failed");
+                record.proving = ProvingStatus::Failed(format!("{:#}", e));
+                self.set(&hash, &record)
+            }
+        }
+    }
+}
+
+/// Replays the execution on the state it was executed on and proves its function calls.
+fn prove_job(
+    config: &Arc<RuntimeConfig>,
+    state: &Arc<RwLock<State>>,
+    prover: &dyn Prover,
+    record: &TransactionRecord,
+) -> Result<Vec<ProofBundle>> {
+    let view = Arc::new(RwLock::new(state.read().unwrap().at_root(record.root)));
+    let tx = match &record.job {
+        ProvingJob::Transaction(tx) => tx,
+        ProvingJob::Call {
+            signer,
+            caller,
+            call,
+        } => {
+            let execution =
+                Node::execute_on(config, &view, signer.clone(), caller.clone(), call.clone())?;
+            let call = executor::prove(&execution.context.read().unwrap(), prover)?;
+            return Ok(vec![ProofBundle { call }]);
+        }
+    };
+    let mut created = BTreeSet::new();
+    let mut bundles = Vec::new();
+
+    for action in tx.actions.iter() {
+        match action {
+            Action::FunctionCall(call) => {
+                let execution = Node::execute_on(
+                    config,
+                    &view,
+                    tx.signer.clone(),
+                    tx.signer.clone(),
+                    call.clone(),
+                )?;
+                let call = executor::prove(&execution.context.read().unwrap(), prover)?;
+                bundles.push(ProofBundle { call });
+            }
+            action => transaction::apply_action(
+                &mut view.write().unwrap(),
+                &tx.signer,
+                action,
+                &mut created,
+            )?,
+        }
+    }
+
+    Ok(bundles)
+}
@@ -0,0 +1,307 @@
+//! Proving over HTTP, with a service which proves the sessions sent to it in the background.
+//!
+//! The protocol:
+//! - `POST /jobs` with an encoded [`ProvingRequest`] queues a job and returns a [`ProvingJob`].
+//! - `GET /jobs/<ID>` returns the [`ProvingJob`] with its status.
+//! - `GET /jobs/<ID>/receipt` returns the encoded `SessionReceipt` of a proven job, and forgets
+//!   the job.
+//!
+//! Requests and receipts are encoded with the zkVM serde format, as little-endian words.
+
+use anyhow::{anyhow, bail, Context, Result};
+use risc0_zkvm::{sha::Digest, ExitCode, Segment, Session, SessionReceipt, SimpleSegmentRef};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use spin_runtime::prover::Prover;
+use spin_verifier::CallReceipt;
+use tracing::{debug, info, warn};
+
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+pub const DEFAULT_PROVER_ADDR: &str = "127.0.0.1:3040";
+
+const JOB_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Executed session to prove: the memory images of its segments, with the inputs and syscall
+/// replies they recorded, and the journal they commit.
+#[derive(Serialize, Deserialize)]
+pub struct ProvingRequest {
+    pub image_id: Digest,
+    pub segments: Vec<Segment>,
+    pub journal: Vec<u8>,
+    pub exit_code: ExitCode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Proving,
+    Proven,
+    Failed(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProvingJob {
+    pub id: u64,
+    pub status: JobStatus,
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    Ok(risc0_zkvm::serde::to_vec(value)?
+        .into_iter()
+        .flat_map(u32::to_le_bytes)
+        .collect())
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    if bytes.len() % 4 != 0 {
+        bail!("Encoded value is truncated");
+    }
+    let words = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    Ok(risc0_zkvm::serde::from_slice(&words)?)
+}
+
+/// Prover sending sessions to a proving service and polling it until they are proven.
+pub struct RemoteProver {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl RemoteProver {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    fn job(&self, id: u64) -> Result<ProvingJob> {
+        Ok(self
+            .agent
+            .get(&format!("{}/jobs/{}", self.url, id))
+            .call()?
+            .into_json()?)
+    }
+}
+
+impl Prover for RemoteProver {
+    fn prove(&self, image_id: Digest, session: &Session) -> Result<CallReceipt> {
+        let request = ProvingRequest {
+            image_id,
+            segments: session
+                .segments
+                .iter()
+                .map(|segment| segment.resolve())
+                .collect::<Result<_>>()?,
+            journal: session.journal.clone(),
+            exit_code: session.exit_code,
+        };
+
+        let job: ProvingJob = self
+            .agent
+            .post(&format!("{}/jobs", self.url))
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(&encode(&request)?)
+            .with_context(|| format!("Can't send proving job to {}", self.url))?
+            .into_json()?;
+        debug!(id = job.id, url = self.url, "Proving job sent");
+
+        loop {
+            match self.job(job.id)?.status {
+                JobStatus::Queued | JobStatus::Proving => thread::sleep(JOB_POLL_INTERVAL),
+                JobStatus::Proven => break,
+                JobStatus::Failed(error) => bail!("Proving job {} failed: {}", job.id, error),
+            }
+        }
+
+        let mut bytes = Vec::new();
+        self.agent
+            .get(&format!("{}/jobs/{}/receipt", self.url, job.id))
+            .call()?
+            .into_reader()
+            .read_to_end(&mut bytes)?;
+        let receipt: SessionReceipt = decode(&bytes)?;
+
+        // The service is not trusted to prove the session it was sent.
+        receipt
+            .verify(image_id)
+            .map_err(|e| anyhow!("Invalid receipt of proving job {}: {}", job.id, e))?;
+        if receipt.journal != session.journal {
+            bail!("Receipt of proving job {} has another journal", job.id);
+        }
+        Ok(CallReceipt::Session(receipt))
+    }
+}
+
+enum Job {
+    Queued,
+    Proving,
+    Proven(Vec<u8>),
+    Failed(String),
+}
+
+impl Job {
+    fn status(&self) -> JobStatus {
+        match self {
+            Job::Queued => JobStatus::Queued,
+            Job::Proving => JobStatus::Proving,
+            Job::Proven(_) => JobStatus::Proven,
+            Job::Failed(error) => JobStatus::Failed(error.clone()),
+        }
+    }
+}
+
+type Jobs = Arc<Mutex<BTreeMap<u64, Job>>>;
+
+fn prove_request(request: ProvingRequest) -> Result<Vec<u8>> {
+    let segments = request
+        .segments
+        .into_iter()
+        .map(|segment| Box::new(SimpleSegmentRef::new(segment)) as _)
+        .collect();
+    let session = Session::new(segments, request.journal, request.exit_code);
+    let receipt = session.prove()?;
+    receipt
+        .verify(request.image_id)
+        .map_err(|e| anyhow!("Session doesn't run image {}: {}", request.image_id, e))?;
+    encode(&receipt)
+}
+
+fn json_response(value: &impl Serialize) -> tiny_http::ResponseBox {
+    tiny_http::Response::from_string(serde_json::to_string(value).expect("Expected to serialize"))
+        .with_header(
+            tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+        )
+        .boxed()
+}
+
+fn error_response(status: u16, message: impl Into<String>) -> tiny_http::ResponseBox {
+    tiny_http::Response::from_string(message)
+        .with_status_code(status)
+        .boxed()
+}
+
+fn handle(
+    jobs: &Jobs,
+    next_id: &mut u64,
+    queue: &mpsc::Sender<(u64, ProvingRequest)>,
+    request: &mut tiny_http::Request,
+) -> tiny_http::ResponseBox {
+    let method = request.method().clone();
+    let path = request.url().trim_end_matches('/').to_string();
+    let segments = path.trim_start_matches('/').split('/').collect::<Vec<_>>();
+
+    match (&method, segments.as_slice()) {
+        (tiny_http::Method::Post, ["jobs"]) => {
+            let mut body = Vec::new();
+            if let Err(e) = request.as_reader().read_to_end(&mut body) {
+                return error_response(400, e.to_string());
+            }
+            let proving_request = match decode::<ProvingRequest>(&body) {
+                Ok(proving_request) => proving_request,
+                Err(e) => return error_response(400, format!("Invalid proving request: {:#}", e)),
+            };
+
+            let id = *next_id;
+            *next_id += 1;
+            jobs.lock().unwrap().insert(id, Job::Queued);
+            queue
+                .send((id, proving_request))
+                .expect("Expected proving workers to run");
+            info!(id, "Proving job queued");
+
+            json_response(&ProvingJob {
+                id,
+                status: JobStatus::Queued,
+            })
+        }
+        (tiny_http::Method::Get, ["jobs", id]) => {
+            let id = match id.parse::<u64>() {
+                Ok(id) => id,
+                Err(_) => return error_response(400, "Invalid job id"),
+            };
+            match jobs.lock().unwrap().get(&id) {
+                Some(job) => json_response(&ProvingJob {
+                    id,
+                    status: job.status(),
+                }),
+                None => error_response(404, format!("Job {} not found", id)),
+            }
+        }
+        (tiny_http::Method::Get, ["jobs", id, "receipt"]) => {
+            let id = match id.parse::<u64>() {
+                Ok(id) => id,
+                Err(_) => return error_response(400, "Invalid job id"),
+            };
+            let mut jobs = jobs.lock().unwrap();
+            match jobs.remove(&id) {
+                Some(Job::Proven(receipt)) => tiny_http::Response::from_data(receipt).boxed(),
+                Some(job) => {
+                    jobs.insert(id, job);
+                    error_response(409, format!("Job {} is not proven", id))
+                }
+                None => error_response(404, format!("Job {} not found", id)),
+            }
+        }
+        _ => error_response(404, format!("Unknown route {} {}", method, path)),
+    }
+}
+
+/// Serves the proving protocol on the address, proving up to `workers` jobs at a time.
+///
+/// Jobs are kept in memory, a proven job is forgotten once its receipt is fetched.
+pub fn serve(addr: &str, workers: usize) -> Result<()> {
+    let jobs: Jobs = Arc::default();
+    let (queue, receiver) = mpsc::channel::<(u64, ProvingRequest)>();
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    for worker in 0..workers.max(1) {
+        let jobs = jobs.clone();
+        let receiver = receiver.clone();
+
+        thread::Builder::new()
+            .name(format!("prover-{}", worker))
+            .spawn(move || loop {
+                let (id, request) = match receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => return,
+                };
+                jobs.lock().unwrap().insert(id, Job::Proving);
+                debug!(worker, id, "Proving job");
+
+                let job = match prove_request(request) {
+                    Ok(receipt) => {
+                        info!(id, "Proving job proven");
+                        Job::Proven(receipt)
+                    }
+                    Err(e) => {
+                        warn!(id, error = ?e, "Proving job failed");
+                        Job::Failed(format!("{:#}", e))
+                    }
+                };
+                jobs.lock().unwrap().insert(id, job);
+            })?;
+    }
+
+    let server = tiny_http::Server::http(addr)
+        .map_err(|e| anyhow!("Can't start prover server on {}: {}", addr, e))?;
+    info!(addr, workers, "Prover server started");
+
+    let mut next_id = 0;
+    for mut request in server.incoming_requests() {
+        let response = handle(&jobs, &mut next_id, &queue, &mut request);
+        if let Err(e) = request.respond(response) {
+            warn!(error = ?e, "Can't send prover response");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use spin_primitives::{AccountId, PublicKey};
+
+use crate::Node;
+
+pub const DEFAULT_RPC_ADDR: &str = "127.0.0.1:3030";
+
+const PARSE_ERROR: i64 = -32700;
+const SERVER_ERROR: i64 = -32000;
+
+/// Unsigned call, only executed as a view; state-changing calls are signed transactions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CallRequest {
+    pub signer: AccountId,
+    pub contract: AccountId,
+    pub method: String,
+    /// Borsh-encoded method arguments.
+    #[serde(with = "hex")]
+    pub args: Vec<u8>,
+    pub attached_gas: u64,
+    /// Block to execute the call at the end of, the latest state if not set.
+    #[serde(default)]
+    pub block_height: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CallOutcome {
+    /// Borsh-encoded output, `None` if the contract didn't commit anything.
+    pub output: Option<String>,
+    pub cross_calls_hashes: Vec<String>,
+    pub gas_used: u64,
+    /// Ids of the asynchronous calls scheduled by the call.
+    #[serde(default)]
+    pub receipts: Vec<u64>,
+    /// Ids of the calls scheduled for future blocks by the call.
+    #[serde(default)]
+    pub scheduled_calls: Vec<u64>,
+}
+
+/// Outcome of a transaction whose actions all succeeded.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TransactionOutcome {
+    /// Hash of the transaction, or of the call the node executed on its own, as hex, to look up
+    /// the record and its proofs.
+    #[serde(default)]
+    pub hash: String,
+    /// Outcome of every action, in order; only function calls have an output and use gas.
+    pub actions: Vec<CallOutcome>,
+    pub gas_used: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionRequest {
+    /// Transaction hash as hex.
+    pub hash: String,
+}
+
+impl TransactionRequest {
+    pub fn decoded_hash(&self) -> Result<[u8; 32]> {
+        hex::decode(self.hash.trim_start_matches("0x"))?
+            .try_into()
+            .map_err(|_| anyhow!("Transaction hash must be 32 bytes"))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionProofsResponse {
+    /// Proof bundle files of the function calls of the transaction as hex, in order.
+    pub bundles: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockProofRequest {
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockProofResponse {
+    /// Block proof file as hex.
+    pub bundle: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccessKeyRequest {
+    pub account: AccountId,
+    pub public_key: PublicKey,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountRequest {
+    pub account: AccountId,
+    /// Block to read the account at the end of, the latest state if not set.
+    #[serde(default)]
+    pub block_height: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StorageRequest {
+    pub contract: AccountId,
+    /// Block to read the storage at the end of, the latest state if not set.
+    #[serde(default)]
+    pub block_height: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StorageProofRequest {
+    pub contract: AccountId,
+    pub key: String,
+    /// Committed state root as hex to prove against, the latest one if not set.
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StorageProofResponse {
+    /// State root as hex.
+    pub root: String,
+    /// Value as hex, `None` if the key has no value.
+    pub value: Option<String>,
+    /// Borsh-encoded `StateProof` as hex.
+    pub proof: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StorageEntry {
+    pub key: String,
+    #[serde(with = "hex")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+impl RpcRequest {
+    pub fn new(method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: String::from("2.0"),
+            id: Value::from(1),
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcResponse {
+    fn result(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: String::from("2.0"),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    fn error(id: Value, code: i64, message: String) -> Self {
+        Self {
+            jsonrpc: String::from("2.0"),
+            id,
+            result: None,
+            error: Some(RpcError { code, message }),
+        }
+    }
+}
+
+/// Handles a single JSON-RPC request body and returns the response body.
+pub fn handle(node: &Node, body: &str) -> String {
+    let response = match serde_json::from_str::<RpcRequest>(body) {
+        Ok(request) => match dispatch(node, &request.method, request.params) {
+            Ok(result) => RpcResponse::result(request.id, result),
+            Err(e) => {
+                tracing::warn!(method = request.method, error = ?e, "RPC call failed");
+                RpcResponse::error(request.id, SERVER_ERROR, format!("{:#}", e))
+            }
+        },
+        Err(e) => RpcResponse::error(Value::Null, PARSE_ERROR, e.to_string()),
+    };
+
+    serde_json::to_string(&response).expect("Expected to serialize")
+}
+
+fn dispatch(node: &Node, method: &str, params: Value) -> Result<Value> {
+    let result = match method {
+        "send_transaction" => {
+            serde_json::to_value(node.send_transaction(serde_json::from_value(params)?)?)?
+        }
+        "view" => serde_json::to_value(node.view(serde_json::from_value(params)?)?)?,
+        "storage" => serde_json::to_value(node.storage(serde_json::from_value(params)?)?)?,
+        "storage_proof" => {
+            serde_json::to_value(node.storage_proof(serde_json::from_value(params)?)?)?
+        }
+        "account" => serde_json::to_value(node.account(serde_json::from_value(params)?)?)?,
+        "block" => serde_json::to_value(node.block()?)?,
+        "transaction" => serde_json::to_value(node.transaction(serde_json::from_value(params)?)?)?,
+        "transaction_proofs" => {
+            serde_json::to_value(node.transaction_proofs(serde_json::from_value(params)?)?)?
+        }
+        "block_proof" => serde_json::to_value(node.block_proof(serde_json::from_value(params)?)?)?,
+        "access_key" => serde_json::to_value(node.access_key(serde_json::from_value(params)?)?)?,
+        _ => return Err(anyhow!("Unknown method {:?}", method)),
+    };
+
+    Ok(result)
+}
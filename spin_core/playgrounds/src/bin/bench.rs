@@ -0,0 +1,373 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use ethabi::token::{LenientTokenizer, Tokenizer};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use spin_primitives::{AccountId, ExecutionCommittment, FunctionCall};
+use spin_runtime::{
+    config::{RuntimeConfig, StateBackend},
+    context::ExecutionContext,
+    executor,
+    genesis::Genesis,
+    prover::LocalProver,
+    state::{State, EVM_CONTRACT},
+};
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+const DEFAULT_ATTACHED_GAS: u64 = 100_000_000;
+
+#[derive(Parser)]
+#[command(
+    about = "Runs contract calls on a fresh genesis state and reports the resources they use"
+)]
+struct Args {
+    /// Benchmark file listing the calls
+    #[arg(default_value = "./etc/bench.toml")]
+    file: PathBuf,
+
+    /// Also prove every call and report the proving time
+    #[arg(long)]
+    prove: bool,
+
+    /// File to write the JSON report to, stdout by default
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Runtime config file, the default config if not set; its state backend is ignored
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct BenchFile {
+    /// Genesis the state is built from, relative to the benchmark file.
+    genesis: PathBuf,
+    calls: Vec<BenchCall>,
+}
+
+/// Step of the benchmark, run in order and committed if it succeeds.
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum BenchCall {
+    /// Call of a contract method with Borsh-encoded arguments as hex.
+    Call {
+        name: String,
+        signer: AccountId,
+        contract: AccountId,
+        method: String,
+        #[serde(default)]
+        args: String,
+        gas: Option<u64>,
+    },
+    /// Deployment of an EVM contract with the EVM meta-contract, named for later calls.
+    EvmDeploy {
+        name: String,
+        signer: AccountId,
+        abi: PathBuf,
+        bytecode: PathBuf,
+        gas: Option<u64>,
+    },
+    /// Call of a deployed EVM contract function, with arguments parsed by its ABI.
+    EvmCall {
+        name: String,
+        signer: AccountId,
+        contract: String,
+        function: String,
+        #[serde(default)]
+        args: Vec<String>,
+        gas: Option<u64>,
+    },
+}
+
+#[derive(Serialize)]
+struct CallReport {
+    name: String,
+    cycles: u64,
+    segments: u64,
+    syscalls: BTreeMap<String, u64>,
+    storage_bytes_read: u64,
+    storage_bytes_written: u64,
+    gas: u64,
+    execution_time_ms: f64,
+    /// Set when the benchmark is run with `--prove`.
+    proving_time_ms: Option<f64>,
+    /// Borsh-encoded output as hex.
+    output: Option<String>,
+    /// Set when the call failed, its state changes are reverted.
+    error: Option<String>,
+}
+
+struct EvmContract {
+    abi: ethabi::Contract,
+    address: eth_primitive_types::H160,
+}
+
+struct Bench {
+    config: Arc<RuntimeConfig>,
+    state: Arc<RwLock<State>>,
+    base_dir: PathBuf,
+    prove: bool,
+    evm_contracts: BTreeMap<String, EvmContract>,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Bench {
+    fn run(&self, call: &BenchCall) -> CallReport {
+        let name = call.name();
+        let (signer, contract, function_call, gas) = match self.function_call(call) {
+            Ok(call) => call,
+            Err(e) => return CallReport::failed(name, e),
+        };
+
+        let context = Arc::new(RwLock::new(ExecutionContext::new(
+            self.config.clone(),
+            self.state.clone(),
+            signer.clone(),
+            signer,
+            contract,
+            gas,
+            function_call,
+        )));
+
+        let result = executor::execute(context.clone()).and_then(|session| {
+            let committment = (!session.journal.is_empty())
+                .then(|| ExecutionCommittment::try_from_bytes(session.journal.clone()))
+                .transpose()?;
+            context.write().unwrap().set_execution_session(session);
+
+            let proving_time = if self.prove {
+                let started = Instant::now();
+                executor::prove(&context.read().unwrap(), &LocalProver)?;
+                Some(started.elapsed())
+            } else {
+                None
+            };
+            Ok((committment, proving_time))
+        });
+
+        let mut state = self.state.write().unwrap();
+        let (output, proving_time, error) = match result {
+            Ok((committment, proving_time)) => match state.commit() {
+                Ok(_) => (
+                    committment.map(|committment| committment.output),
+                    proving_time,
+                    None,
+                ),
+                Err(e) => (None, None, Some(e)),
+            },
+            Err(e) => {
+                state.rollback();
+                (None, None, Some(e))
+            }
+        };
+
+        let context = context.read().unwrap();
+        let stats = context.total_stats();
+        CallReport {
+            name,
+            cycles: stats.cycles,
+            segments: stats.segments,
+            syscalls: stats.syscalls,
+            storage_bytes_read: stats.storage_bytes_read,
+            storage_bytes_written: stats.storage_bytes_written,
+            gas: context.total_used_gas(),
+            execution_time_ms: millis(stats.execution_time),
+            proving_time_ms: proving_time.map(millis),
+            output: output.as_ref().map(hex::encode),
+            error: error.map(|e| format!("{:#}", e)),
+        }
+    }
+
+    /// Signer, contract, function call and attached gas of the step.
+    fn function_call(&self, call: &BenchCall) -> Result<(AccountId, AccountId, FunctionCall, u64)> {
+        let evm = AccountId::new(EVM_CONTRACT.to_string());
+
+        Ok(match call {
+            BenchCall::Call {
+                signer,
+                contract,
+                method,
+                args,
+                gas,
+            } => (
+                signer.clone(),
+                contract.clone(),
+                FunctionCall {
+                    method: method.clone(),
+                    args: hex::decode(args)?,
+                },
+                gas.unwrap_or(DEFAULT_ATTACHED_GAS),
+            ),
+            BenchCall::EvmDeploy {
+                signer,
+                abi,
+                bytecode,
+                gas,
+            } => {
+                let abi =
+                    ethabi::Contract::load(std::fs::read(self.base_dir.join(abi))?.as_slice())?;
+                let bytecode = hex::decode(std::fs::read(self.base_dir.join(bytecode))?)?;
+                let input = match abi.constructor() {
+                    Some(constructor) => constructor.encode_input(bytecode, &[])?,
+                    None => bytecode,
+                };
+                (
+                    signer.clone(),
+                    evm,
+                    FunctionCall::new("deploy_contract".into(), input),
+                    gas.unwrap_or(DEFAULT_ATTACHED_GAS),
+                )
+            }
+            BenchCall::EvmCall {
+                signer,
+                contract,
+                function,
+                args,
+                gas,
+            } => {
+                let contract = self
+                    .evm_contracts
+                    .get(contract)
+                    .ok_or_else(|| anyhow!("EVM contract {} is not deployed", contract))?;
+                let function = contract.abi.function(function)?;
+                let tokens = function
+                    .inputs
+                    .iter()
+                    .zip(args.iter())
+                    .map(|(param, arg)| LenientTokenizer::tokenize(&param.kind, arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let input = function.encode_input(&tokens)?;
+                (
+                    signer.clone(),
+                    evm,
+                    FunctionCall::new(
+                        "call_contract".into(),
+                        (contract.address.to_fixed_bytes(), input),
+                    ),
+                    gas.unwrap_or(DEFAULT_ATTACHED_GAS),
+                )
+            }
+        })
+    }
+
+    /// Remembers the address of a deployed EVM contract for the calls of the next steps.
+    fn record_deployment(&mut self, call: &BenchCall, report: &CallReport) -> Result<()> {
+        if let (BenchCall::EvmDeploy { name, abi, .. }, Some(output)) = (call, &report.output) {
+            let (address, _): ([u8; 20], Vec<u8>) =
+                borsh::BorshDeserialize::try_from_slice(&hex::decode(output)?)?;
+            let abi = ethabi::Contract::load(std::fs::read(self.base_dir.join(abi))?.as_slice())?;
+            info!(
+                name,
+                address = hex::encode(address),
+                "EVM contract deployed"
+            );
+            self.evm_contracts.insert(
+                name.clone(),
+                EvmContract {
+                    abi,
+                    address: eth_primitive_types::H160::from(address),
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+impl BenchCall {
+    fn name(&self) -> String {
+        match self {
+            BenchCall::Call { name, .. }
+            | BenchCall::EvmDeploy { name, .. }
+            | BenchCall::EvmCall { name, .. } => name.clone(),
+        }
+    }
+}
+
+impl CallReport {
+    fn failed(name: String, error: anyhow::Error) -> Self {
+        Self {
+            name,
+            cycles: 0,
+            segments: 0,
+            syscalls: BTreeMap::new(),
+            storage_bytes_read: 0,
+            storage_bytes_written: 0,
+            gas: 0,
+            execution_time_ms: 0.0,
+            proving_time_ms: None,
+            output: None,
+            error: Some(format!("{:#}", error)),
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<Vec<CallReport>> {
+    let file = std::fs::read_to_string(&args.file)
+        .with_context(|| format!("Can't read benchmark {:?}", args.file))?;
+    let bench_file: BenchFile =
+        toml::from_str(&file).with_context(|| format!("Can't parse benchmark {:?}", args.file))?;
+    let base_dir = args.file.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut config = RuntimeConfig::resolve(args.config.as_deref(), None)?;
+    config.state = StateBackend::Temporary;
+    let config = Arc::new(config);
+
+    let state = Arc::new(RwLock::new(config.state.open()?));
+    let genesis_path = base_dir.join(&bench_file.genesis);
+    let genesis_dir = genesis_path.parent().unwrap_or_else(|| Path::new("."));
+    Genesis::load(&genesis_path)?.build(genesis_dir, &config, state.clone())?;
+
+    let mut bench = Bench {
+        config,
+        state,
+        base_dir: base_dir.to_path_buf(),
+        prove: args.prove,
+        evm_contracts: BTreeMap::new(),
+    };
+
+    let mut reports = Vec::new();
+    for call in bench_file.calls.iter() {
+        info!(name = call.name(), "Running benchmark call");
+        let report = bench.run(call);
+        bench.record_deployment(call, &report)?;
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+/// Logs to stderr, which keeps the report on stdout parseable.
+fn install_tracing() {
+    use tracing_subscriber::{fmt, prelude::*, registry, EnvFilter};
+
+    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "warn,bench=info".to_owned());
+
+    let main_layer = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_filter(EnvFilter::from(filter));
+
+    registry().with(main_layer).init();
+}
+
+fn main() -> Result<()> {
+    install_tracing();
+
+    let args = Args::parse();
+    let reports = run(&args)?;
+
+    let report = serde_json::to_string_pretty(&reports)?;
+    match &args.out {
+        Some(out) => std::fs::write(out, report)?,
+        None => println!("{}", report),
+    }
+    Ok(())
+}
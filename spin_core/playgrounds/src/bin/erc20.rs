@@ -1,8 +1,10 @@
 use tracing::info;
 
 use spin_primitives::{AccountId, ExecutionCommittment};
+use spin_runtime::config::RuntimeConfig;
 use spin_runtime::context::ExecutionContext;
 use spin_runtime::executor;
+use spin_runtime::state::State;
 
 use playgrounds::install_tracing;
 
@@ -11,26 +13,33 @@ use std::sync::{Arc, RwLock};
 fn main() {
     install_tracing();
 
+    let state = Arc::new(RwLock::new(RuntimeConfig::default().state.open().unwrap()));
+
     let abi_path = String::from("./etc/evm_contracts/erc20.abi");
     let bytecode_path = String::from("./etc/evm_contracts/erc20_bytecode");
 
     let alice = AccountId::new("alice.spin".to_string());
-    let alice_evm_address = ExecutionContext::get_account_evm_address(alice.clone());
+    let alice_evm_address = state.read().unwrap().evm_address(&alice).unwrap();
 
     let abi = ethabi::Contract::load(std::fs::read(abi_path).unwrap().as_slice()).unwrap();
 
-    // init_evm_accounts();
-    // info!("EVM accounts initialized");
-
-    let token_address = deploy_evm_contract(&abi, bytecode_path, &alice);
+    let token_address = deploy_evm_contract(&state, &abi, bytecode_path, &alice);
     info!(?token_address, "token deployed");
 
-    let token_owner = call_evm_contract(&abi, token_address, String::from("owner"), &[], &alice);
+    let token_owner = call_evm_contract(
+        &state,
+        &abi,
+        token_address,
+        String::from("owner"),
+        &[],
+        &alice,
+    );
 
     assert!(token_owner[0].clone().into_address().unwrap().0 == alice_evm_address.to_fixed_bytes());
     info!("Token owner is alice");
 
     let alice_balance = call_evm_contract(
+        &state,
         &abi,
         token_address,
         String::from("balanceOf"),
@@ -40,6 +49,7 @@ fn main() {
     info!(?alice_balance, "Alice balance");
 
     call_evm_contract(
+        &state,
         &abi,
         token_address,
         String::from("mint"),
@@ -52,6 +62,7 @@ fn main() {
     info!("Alice minted 100 tokens");
 
     let alice_balance = call_evm_contract(
+        &state,
         &abi,
         token_address,
         String::from("balanceOf"),
@@ -62,21 +73,9 @@ fn main() {
     assert!(alice_balance[0].clone().into_uint().unwrap() == 100.into());
 }
 
-#[allow(dead_code)]
-fn init_evm_accounts() {
-    let ctx = Arc::new(RwLock::new(ExecutionContext::new(
-        AccountId::new(String::from("alice.spin")),
-        AccountId::new(String::from("alice.spin")),
-        AccountId::new("evm".to_string()),
-        100_000_000,
-        spin_primitives::FunctionCall::new("init".into(), ()),
-    )));
-
-    executor::execute(ctx.clone()).unwrap();
-}
-
 /// Deploy EVM contract and return its address
 fn deploy_evm_contract(
+    state: &Arc<RwLock<State>>,
     abi: &ethabi::Contract,
     hex_bytecode_path: String,
     owner_account_id: &AccountId,
@@ -88,6 +87,8 @@ fn deploy_evm_contract(
     let constructor_input = constructor.encode_input(bytecode, &[]).unwrap();
 
     let ctx = Arc::new(RwLock::new(ExecutionContext::new(
+        Arc::new(RuntimeConfig::default()),
+        state.clone(),
         owner_account_id.clone(),
         owner_account_id.clone(),
         AccountId::new("evm".to_string()),
@@ -96,6 +97,8 @@ fn deploy_evm_contract(
     )));
 
     let s = executor::execute(ctx.clone()).unwrap();
+    state.write().unwrap().commit().unwrap();
+
     let committment: ExecutionCommittment =
         borsh::BorshDeserialize::deserialize(&mut s.journal.as_slice()).unwrap();
 
@@ -106,6 +109,7 @@ fn deploy_evm_contract(
 }
 
 fn call_evm_contract(
+    state: &Arc<RwLock<State>>,
     abi: &ethabi::Contract,
     contract_address: eth_primitive_types::H160,
     function: String,
@@ -116,6 +120,8 @@ fn call_evm_contract(
     let input = function.encode_input(args).unwrap();
 
     let ctx = Arc::new(RwLock::new(ExecutionContext::new(
+        Arc::new(RuntimeConfig::default()),
+        state.clone(),
         account_id.clone(),
         account_id.clone(),
         AccountId::new("evm".to_string()),
@@ -127,6 +133,8 @@ fn call_evm_contract(
     )));
 
     let s = executor::execute(ctx.clone()).unwrap();
+    state.write().unwrap().commit().unwrap();
+
     let committment: ExecutionCommittment =
         borsh::BorshDeserialize::deserialize(&mut s.journal.as_slice()).unwrap();
 
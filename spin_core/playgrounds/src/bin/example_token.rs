@@ -1,8 +1,10 @@
 use tracing::info;
 
 use spin_primitives::{AccountId, ExecutionCommittment};
+use spin_runtime::config::RuntimeConfig;
 use spin_runtime::context::ExecutionContext;
 use spin_runtime::executor;
+use spin_runtime::state::State;
 
 use playgrounds::install_tracing;
 
@@ -11,28 +13,36 @@ use std::sync::{Arc, RwLock};
 fn main() {
     install_tracing();
 
+    let state = Arc::new(RwLock::new(RuntimeConfig::default().state.open().unwrap()));
+
     let token = AccountId::new("token.spin".to_string());
     let alice = AccountId::new("alice.spin".to_string());
     let bob = AccountId::new("bob.spin".to_string());
 
-    token_init(&token, &alice, String::from("SPIN"), 100);
+    token_init(&state, &token, &alice, String::from("SPIN"), 100);
 
-    let alice_balance = token_balance_of(&token, &alice);
+    let alice_balance = token_balance_of(&state, &token, &alice);
     info!(address = ?alice, balance = alice_balance);
 
-    let bob_balance = token_balance_of(&token, &bob);
+    let bob_balance = token_balance_of(&state, &token, &bob);
     info!(address = ?bob, balance = bob_balance);
 
-    transfer(&token, &alice, &bob, 10);
+    transfer(&state, &token, &alice, &bob, 10);
 
-    let alice_balance = token_balance_of(&token, &alice);
+    let alice_balance = token_balance_of(&state, &token, &alice);
     info!(address = ?alice, balance = alice_balance);
 
-    let bob_balance = token_balance_of(&token, &bob);
+    let bob_balance = token_balance_of(&state, &token, &bob);
     info!(address = ?bob, balance = bob_balance);
 }
 
-fn token_init(token: &AccountId, signer: &AccountId, ticker: String, initial_supply: u128) {
+fn token_init(
+    state: &Arc<RwLock<State>>,
+    token: &AccountId,
+    signer: &AccountId,
+    ticker: String,
+    initial_supply: u128,
+) {
     info!(
         ?token,
         owner = ?signer,
@@ -41,6 +51,8 @@ fn token_init(token: &AccountId, signer: &AccountId, ticker: String, initial_sup
         "Creating token"
     );
     let ctx = Arc::new(RwLock::new(ExecutionContext::new(
+        Arc::new(RuntimeConfig::default()),
+        state.clone(),
         signer.clone(),
         signer.clone(),
         token.clone(),
@@ -49,11 +61,20 @@ fn token_init(token: &AccountId, signer: &AccountId, ticker: String, initial_sup
     )));
 
     executor::execute(ctx).unwrap();
+    state.write().unwrap().commit().unwrap();
 }
 
-fn transfer(token: &AccountId, from: &AccountId, to: &AccountId, amount: u128) {
+fn transfer(
+    state: &Arc<RwLock<State>>,
+    token: &AccountId,
+    from: &AccountId,
+    to: &AccountId,
+    amount: u128,
+) {
     info!(amount, ?to, ?from, "Transfering");
     let ctx = Arc::new(RwLock::new(ExecutionContext::new(
+        Arc::new(RuntimeConfig::default()),
+        state.clone(),
         AccountId::new(from.to_string()),
         AccountId::new(from.to_string()),
         AccountId::new(token.to_string()),
@@ -62,10 +83,13 @@ fn transfer(token: &AccountId, from: &AccountId, to: &AccountId, amount: u128) {
     )));
 
     executor::execute(ctx).unwrap();
+    state.write().unwrap().commit().unwrap();
 }
 
-fn token_balance_of(token: &AccountId, account: &AccountId) -> u64 {
+fn token_balance_of(state: &Arc<RwLock<State>>, token: &AccountId, account: &AccountId) -> u64 {
     let ctx = Arc::new(RwLock::new(ExecutionContext::new(
+        Arc::new(RuntimeConfig::default()),
+        state.clone(),
         account.clone(),
         account.clone(),
         token.clone(),
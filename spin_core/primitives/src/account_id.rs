@@ -0,0 +1,183 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use risc0_zkvm::sha::rust_crypto::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+pub const MIN_ACCOUNT_ID_LEN: usize = 2;
+pub const MAX_ACCOUNT_ID_LEN: usize = 64;
+
+/// Suffix of accounts owned by EVM addresses, only produced by [`AccountId::new_evm`].
+pub const EVM_SUFFIX: &str = ".evm";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountIdError {
+    TooShort(usize),
+    TooLong(usize),
+    InvalidChar(char),
+    /// Account id starts or ends with a separator or has two of them in a row.
+    InvalidSeparator,
+    ReservedSuffix(&'static str),
+}
+
+impl fmt::Display for AccountIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountIdError::TooShort(len) => write!(
+                f,
+                "account id is {} characters long, at least {} expected",
+                len, MIN_ACCOUNT_ID_LEN
+            ),
+            AccountIdError::TooLong(len) => write!(
+                f,
+                "account id is {} characters long, at most {} expected",
+                len, MAX_ACCOUNT_ID_LEN
+            ),
+            AccountIdError::InvalidChar(c) => write!(f, "account id contains {:?}", c),
+            AccountIdError::InvalidSeparator => {
+                write!(f, "account id has a misplaced separator")
+            }
+            AccountIdError::ReservedSuffix(suffix) => {
+                write!(f, "account id suffix {:?} is reserved", suffix)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AccountIdError {}
+
+/// Id of an account.
+///
+/// Ids are lowercase alphanumeric parts separated by `.`, `-` or `_`, e.g. `alice.spin`.
+/// Every `.`-separated prefix is owned by the rest of the id: only `alice.spin` may create
+/// `app.alice.spin`.
+#[derive(
+    Serialize, Deserialize, Debug, BorshSerialize, Clone, PartialEq, Hash, PartialOrd, Eq, Ord,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct AccountId(String);
+
+impl AccountId {
+    /// Panics if the id is invalid, use [`AccountId::try_from`] for untrusted input.
+    pub fn new(id: String) -> Self {
+        match Self::try_from(id) {
+            Ok(account_id) => account_id,
+            Err(err) => panic!("Invalid account id: {}", err),
+        }
+    }
+
+    pub fn new_evm(address: eth_primitive_types::H160) -> Self {
+        Self(format!("{:?}{}", address, EVM_SUFFIX))
+    }
+
+    pub fn validate(id: &str) -> Result<(), AccountIdError> {
+        if id.len() < MIN_ACCOUNT_ID_LEN {
+            return Err(AccountIdError::TooShort(id.len()));
+        }
+        if id.len() > MAX_ACCOUNT_ID_LEN {
+            return Err(AccountIdError::TooLong(id.len()));
+        }
+
+        let mut last_is_separator = true;
+        for c in id.chars() {
+            match c {
+                'a'..='z' | '0'..='9' => last_is_separator = false,
+                '.' | '-' | '_' if !last_is_separator => last_is_separator = true,
+                '.' | '-' | '_' => return Err(AccountIdError::InvalidSeparator),
+                c => return Err(AccountIdError::InvalidChar(c)),
+            }
+        }
+        if last_is_separator {
+            return Err(AccountIdError::InvalidSeparator);
+        }
+
+        if id.ends_with(EVM_SUFFIX) && !Self::is_evm_id(id) {
+            return Err(AccountIdError::ReservedSuffix(EVM_SUFFIX));
+        }
+
+        Ok(())
+    }
+
+    fn is_evm_id(id: &str) -> bool {
+        id.strip_suffix(EVM_SUFFIX)
+            .and_then(|address| address.strip_prefix("0x"))
+            .map_or(false, |address| {
+                address.len() == 40 && address.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+            })
+    }
+
+    /// Whether the account is owned by an EVM address.
+    pub fn is_evm(&self) -> bool {
+        Self::is_evm_id(&self.0)
+    }
+
+    /// Whether the account has no parent, like `spin` or `evm`.
+    pub fn is_top_level(&self) -> bool {
+        !self.0.contains('.')
+    }
+
+    /// Account which may create this one, `alice.spin` for `app.alice.spin`.
+    pub fn parent(&self) -> Option<AccountId> {
+        self.0
+            .split_once('.')
+            .map(|(_, parent)| Self(parent.to_string()))
+    }
+
+    /// Whether the account is a direct sub-account of `parent`.
+    pub fn is_sub_account_of(&self, parent: &AccountId) -> bool {
+        self.parent().as_ref() == Some(parent)
+    }
+
+    /// EVM address of an account without a registered alias: the last 20 bytes of SHA-256 of its id.
+    pub fn derive_evm_address(&self) -> eth_primitive_types::H160 {
+        let mut hasher = Sha256::default();
+        hasher.update(self.0.as_bytes());
+        eth_primitive_types::H160::from_slice(&hasher.finalize()[12..])
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for AccountId {
+    type Error = AccountIdError;
+
+    fn try_from(id: String) -> Result<Self, Self::Error> {
+        Self::validate(&id)?;
+        Ok(Self(id))
+    }
+}
+
+impl std::str::FromStr for AccountId {
+    type Err = AccountIdError;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        Self::try_from(id.to_string())
+    }
+}
+
+impl From<AccountId> for String {
+    fn from(account_id: AccountId) -> Self {
+        account_id.0
+    }
+}
+
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<eth_primitive_types::H160> for AccountId {
+    fn from(address: eth_primitive_types::H160) -> Self {
+        Self::new_evm(address)
+    }
+}
+
+impl BorshDeserialize for AccountId {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let id = String::deserialize_reader(reader)?;
+        Self::try_from(id).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+}
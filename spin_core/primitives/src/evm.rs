@@ -0,0 +1,11 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// EVM account created by the meta-contract's `init` at genesis.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct EvmAccount {
+    pub address: [u8; 20],
+    pub balance: [u8; 32],
+    pub nonce: [u8; 32],
+    pub code: Vec<u8>,
+    pub storage: Vec<([u8; 32], [u8; 32])>,
+}
@@ -1,9 +1,19 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 
+pub mod account_id;
+pub mod evm;
+pub mod proof;
 pub mod syscalls;
+pub mod transaction;
 
-#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize)]
+pub use account_id::{AccountId, AccountIdError};
+pub use transaction::{
+    AccessKey, AccessKeyPermission, Action, FunctionCallPermission, Signature, SignedTransaction,
+    Transaction,
+};
+
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone)]
 pub struct ContractCall {
     pub account: AccountId,
     pub function_call: FunctionCall,
@@ -29,11 +39,164 @@ impl ContractCall {
     pub fn into_bytes(&self) -> Vec<u8> {
         borsh::BorshSerialize::try_to_vec(&self).expect("Expected to serialize")
     }
+
+    pub fn new_evm_call(
+        address: eth_primitive_types::H160,
+        data: Vec<u8>,
+        attached_gas: u64,
+    ) -> Self {
+        Self::new(
+            address.into(),
+            String::from("contract_call"), // TODO: use const
+            data,
+            attached_gas,
+        )
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize)]
+/// Method called on the scheduling contract once an asynchronous call is executed.
+///
+/// The callback is called with the Borsh-encoded [`PromiseResult`] followed by `args`,
+/// so a method taking `(PromiseResult, T)` receives both.
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone)]
+pub struct Callback {
+    pub method: String,
+    pub args: Vec<u8>,
+    pub attached_gas: u64,
+}
+
+impl Callback {
+    pub fn new<T>(method: String, args: T, attached_gas: u64) -> Self
+    where
+        T: BorshSerialize,
+    {
+        Self {
+            method,
+            args: args.try_to_vec().expect("Expected to serialize"),
+            attached_gas,
+        }
+    }
+}
+
+/// Block the node is producing, calls of user transactions run in the latest one.
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, Default)]
+pub struct BlockInfo {
+    pub height: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Block at which a scheduled call becomes due.
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleTime {
+    BlockHeight(u64),
+    /// Milliseconds since the Unix epoch.
+    Timestamp(u64),
+}
+
+impl ScheduleTime {
+    pub fn is_due(&self, block: &BlockInfo) -> bool {
+        match self {
+            ScheduleTime::BlockHeight(height) => block.height >= *height,
+            ScheduleTime::Timestamp(timestamp) => block.timestamp >= *timestamp,
+        }
+    }
+
+    /// Moves the time forward by `interval`, in blocks or milliseconds.
+    pub fn advance(&self, interval: u64) -> Self {
+        match self {
+            ScheduleTime::BlockHeight(height) => ScheduleTime::BlockHeight(height + interval),
+            ScheduleTime::Timestamp(timestamp) => ScheduleTime::Timestamp(timestamp + interval),
+        }
+    }
+}
+
+/// When and how many times a scheduled call runs.
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    pub at: ScheduleTime,
+    /// Time between runs, in the unit of `at`; required if the call runs more than once.
+    pub interval: Option<u64>,
+    /// Remaining runs, the attached gas of all of them is prepaid by the scheduling call.
+    pub runs: u32,
+}
+
+impl Schedule {
+    pub fn once(at: ScheduleTime) -> Self {
+        Self {
+            at,
+            interval: None,
+            runs: 1,
+        }
+    }
+
+    pub fn recurring(at: ScheduleTime, interval: u64, runs: u32) -> Self {
+        Self {
+            at,
+            interval: Some(interval),
+            runs,
+        }
+    }
+
+    /// Schedule after a run, `None` if it was the last one.
+    pub fn next(&self) -> Option<Self> {
+        match self.interval {
+            Some(interval) if self.runs > 1 => Some(Self {
+                at: self.at.advance(interval),
+                interval: self.interval,
+                runs: self.runs - 1,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Panic message of a contract called with a method it doesn't have.
+pub const UNKNOWN_METHOD_PANIC: &str = "Unknown method name";
+
+/// Failure of a cross-contract call, returned to the caller by `try_cross_contract_call`.
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq)]
+pub enum CallError {
+    /// The callee panicked with the message.
+    Panic(String),
+    /// The callee used up its attached gas, or the caller didn't have that much gas left.
+    OutOfGas,
+    MethodNotFound(String),
+    ContractNotFound(AccountId),
+    /// The execution failed for another reason.
+    Other(String),
+    /// The call would nest cross-contract calls deeper than the runtime allows.
+    CallDepthExceeded,
+}
+
+impl std::fmt::Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallError::Panic(message) => write!(f, "Contract panicked: {}", message),
+            CallError::OutOfGas => write!(f, "Out of gas"),
+            CallError::MethodNotFound(method) => write!(f, "Method {} not found", method),
+            CallError::ContractNotFound(account) => write!(f, "Contract {} not found", account),
+            CallError::Other(message) => write!(f, "{}", message),
+            CallError::CallDepthExceeded => write!(f, "Call depth exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+/// Outcome of an asynchronous call, passed to its callback.
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq)]
+pub enum PromiseResult {
+    /// Borsh-encoded output of the callee, empty if it didn't commit anything.
+    Successful(Vec<u8>),
+    Failed(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone)]
 pub struct FunctionCall {
     pub method: String,
+    #[serde(with = "hex")]
     pub args: Vec<u8>,
 }
 
@@ -59,69 +222,136 @@ impl FunctionCall {
     pub fn into_bytes(&self) -> Vec<u8> {
         borsh::BorshSerialize::try_to_vec(&self).expect("Expected to serialize")
     }
+
+    pub fn hash(&self) -> [u8; 32] {
+        proof::sha256(&self.into_bytes())
+    }
 }
 
 #[derive(
-    Serialize,
-    Deserialize,
-    Debug,
-    BorshSerialize,
-    BorshDeserialize,
-    Clone,
-    PartialEq,
-    Hash,
-    PartialOrd,
-    Eq,
+    Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq, Hash,
 )]
-pub struct AccountId(String);
+pub struct PublicKey(#[serde(with = "hex")] pub [u8; 32]);
+
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, Default)]
+pub struct Account {
+    pub balance: u128,
+    /// Bytes of contract storage held by the account.
+    #[serde(default)]
+    pub storage_usage: u64,
+}
 
-impl AccountId {
-    pub fn new(id: String) -> Self {
-        Self(id)
+impl Account {
+    /// Part of the balance locked to pay for the storage usage, which can't be transferred.
+    pub fn locked_balance(&self, storage_byte_cost: u128) -> u128 {
+        u128::from(self.storage_usage).saturating_mul(storage_byte_cost)
     }
 
-    pub fn new_evm(address: eth_primitive_types::H160) -> Self {
-        Self(format!("{:?}.evm", address))
+    pub fn available_balance(&self, storage_byte_cost: u128) -> u128 {
+        self.balance
+            .saturating_sub(self.locked_balance(storage_byte_cost))
     }
 
-    pub fn to_string(&self) -> String {
-        self.0.clone()
+    pub fn try_from_bytes(bytes: Vec<u8>) -> std::io::Result<Self> {
+        borsh::BorshDeserialize::deserialize(&mut bytes.as_slice())
     }
-}
 
-impl From<eth_primitive_types::H160> for AccountId {
-    fn from(address: eth_primitive_types::H160) -> Self {
-        Self::new_evm(address)
+    pub fn into_bytes(&self) -> Vec<u8> {
+        borsh::BorshSerialize::try_to_vec(&self).expect("Expected to serialize")
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq)]
 pub struct CallEnv {
     pub signer: AccountId,
     pub caller: AccountId,
     pub contract: AccountId,
     pub attached_gas: u64,
+    pub block: BlockInfo,
 }
 
 impl CallEnv {
     pub fn into_bytes(&self) -> Vec<u8> {
         borsh::BorshSerialize::try_to_vec(&self).expect("Expected to serialize")
     }
+
+    pub fn hash(&self) -> [u8; 32] {
+        proof::sha256(&self.into_bytes())
+    }
 }
 
+/// Version of the [`ExecutionCommittment`] format, committments of other versions are rejected.
+pub const COMMITTMENT_VERSION: u8 = 2;
+
+/// Journal of a call.
+///
+/// The image ID of the contract isn't part of it, since the receipt is verified against it.
 #[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize)]
 pub struct ExecutionCommittment {
+    pub version: u8,
+    /// Hash of the environment the call ran in.
+    pub env_hash: [u8; 32],
+    /// Hash of the method and arguments of the call.
+    pub call_hash: [u8; 32],
+    pub attached_gas: u64,
     pub output: Vec<u8>,
     pub cross_calls_hashes: Vec<[u8; 32]>,
     pub initial_state_hash: Option<[u8; 32]>,
     pub final_state_hash: Option<[u8; 32]>,
+    /// Set when the contract failed the call, whose output is then empty.
+    pub error: Option<CallError>,
 }
 
 impl ExecutionCommittment {
+    pub fn new(
+        env: &CallEnv,
+        call_hash: [u8; 32],
+        output: Vec<u8>,
+        cross_calls_hashes: Vec<[u8; 32]>,
+    ) -> Self {
+        Self {
+            version: COMMITTMENT_VERSION,
+            env_hash: env.hash(),
+            call_hash,
+            attached_gas: env.attached_gas,
+            output,
+            cross_calls_hashes,
+            initial_state_hash: None,
+            final_state_hash: None,
+            error: None,
+        }
+    }
+
+    /// Committment of a call the contract failed with the error, proving the failure.
+    pub fn failed(
+        env: &CallEnv,
+        call_hash: [u8; 32],
+        error: CallError,
+        cross_calls_hashes: Vec<[u8; 32]>,
+    ) -> Self {
+        Self {
+            error: Some(error),
+            ..Self::new(env, call_hash, Vec::new(), cross_calls_hashes)
+        }
+    }
+
     pub fn try_from_bytes(bytes: Vec<u8>) -> std::io::Result<Self> {
+        if bytes.first() != Some(&COMMITTMENT_VERSION) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Unsupported committment version",
+            ));
+        }
         borsh::BorshDeserialize::deserialize(&mut bytes.as_slice())
     }
 
+    /// Checks that the committment was made by a call of the function in the environment.
+    pub fn is_bound_to(&self, env: &CallEnv, call: &FunctionCall) -> bool {
+        self.env_hash == env.hash()
+            && self.call_hash == call.hash()
+            && self.attached_gas == env.attached_gas
+    }
+
     pub fn into_bytes(&self) -> Vec<u8> {
         borsh::BorshSerialize::try_to_vec(&self).expect("Expected to serialize")
     }
@@ -129,27 +359,16 @@ impl ExecutionCommittment {
     pub fn try_deserialize_output<T: BorshDeserialize>(&self) -> std::io::Result<T> {
         borsh::BorshDeserialize::deserialize(&mut self.output.as_slice())
     }
-}
-
-pub struct Transaction {
-    pub signer: AccountId,
-    pub call: ContractCall,
-}
 
-impl Transaction {
-    pub fn new_evm_call(
-        signer: AccountId,
-        address: eth_primitive_types::H160,
-        data: Vec<u8>,
-        attached_gas: u64,
-    ) -> Self {
-        Self {
-            signer,
-            call: ContractCall::new(
-                address.into(),
-                String::from("contract_call"), // TODO: use const
-                data,
-                attached_gas,
+    /// Hash a caller commits to for one of its cross-contract calls: of the journal of the callee,
+    /// or of the Borsh-encoded error a fallible call failed with.
+    pub fn cross_call_hash(result: Result<&[u8], &CallError>) -> [u8; 32] {
+        match result {
+            Ok(journal) => proof::sha256(journal),
+            Err(error) => proof::sha256(
+                &Err::<Vec<u8>, _>(error.clone())
+                    .try_to_vec()
+                    .expect("Expected to serialize"),
             ),
         }
     }
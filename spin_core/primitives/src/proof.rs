@@ -0,0 +1,320 @@
+use std::fmt;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use risc0_zkvm::sha::rust_crypto::{Digest, Sha256};
+
+use crate::AccountId;
+
+pub type Hash = [u8; 32];
+
+/// Root of a tree without leaves, and the hash of an empty subtree.
+pub const EMPTY_HASH: Hash = [0; 32];
+
+const LEAF_PREFIX: u8 = 0;
+const INTERNAL_PREFIX: u8 = 1;
+
+pub fn sha256(data: &[u8]) -> Hash {
+    let mut hasher = Sha256::default();
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+pub fn leaf_hash(key_hash: &Hash, value_hash: &Hash) -> Hash {
+    let mut hasher = Sha256::default();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(key_hash);
+    hasher.update(value_hash);
+    hasher.finalize().into()
+}
+
+pub fn internal_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::default();
+    hasher.update([INTERNAL_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Returns the bit of the key hash which selects the child at `depth`, `true` for the right one.
+pub fn path_bit(key_hash: &Hash, depth: usize) -> bool {
+    (key_hash[depth / 8] >> (7 - depth % 8)) & 1 == 1
+}
+
+/// State key under which the storage value of a contract is committed to.
+pub fn storage_state_key(contract: &AccountId, key: &str) -> Vec<u8> {
+    format!("s/{}/{}", contract, key).into_bytes()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    /// The proof doesn't lead to the expected state root.
+    RootMismatch,
+    /// The proof shows a different value than the expected one.
+    ValueMismatch,
+    /// The leaf at the end of the path doesn't belong there.
+    InvalidLeaf,
+    /// The path is longer than the key hash.
+    TooDeep(usize),
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofError::RootMismatch => write!(f, "proof doesn't match the state root"),
+            ProofError::ValueMismatch => write!(f, "proof doesn't match the value"),
+            ProofError::InvalidLeaf => write!(f, "proof leaf is not on the path of the key"),
+            ProofError::TooDeep(depth) => write!(f, "proof path is {} levels deep", depth),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+/// Leaf of the sparse Merkle tree.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct ProofLeaf {
+    pub key_hash: Hash,
+    pub value_hash: Hash,
+}
+
+/// Proof that a state key has a value, or has none, at a state root.
+///
+/// The path of the key ends either at its own leaf (inclusion), at the leaf of another key
+/// sharing the path prefix, or at an empty subtree (non-inclusion).
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct StateProof {
+    /// Hashes of the siblings along the path, from the root down.
+    pub siblings: Vec<Hash>,
+    /// Leaf at the end of the path, `None` for an empty subtree.
+    pub leaf: Option<ProofLeaf>,
+}
+
+impl StateProof {
+    /// Checks that the state key has the value at the root, or has no value if `value` is `None`.
+    pub fn verify(
+        &self,
+        root: &Hash,
+        state_key: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<(), ProofError> {
+        let key_hash = sha256(state_key);
+        let depth = self.siblings.len();
+        if depth > 256 {
+            return Err(ProofError::TooDeep(depth));
+        }
+
+        let mut node = match &self.leaf {
+            Some(leaf) => {
+                if (0..depth).any(|i| path_bit(&leaf.key_hash, i) != path_bit(&key_hash, i)) {
+                    return Err(ProofError::InvalidLeaf);
+                }
+                leaf_hash(&leaf.key_hash, &leaf.value_hash)
+            }
+            None => EMPTY_HASH,
+        };
+
+        let proven_value_hash = self
+            .leaf
+            .as_ref()
+            .filter(|leaf| leaf.key_hash == key_hash)
+            .map(|leaf| leaf.value_hash);
+        if proven_value_hash != value.map(sha256) {
+            return Err(ProofError::ValueMismatch);
+        }
+
+        for (i, sibling) in self.siblings.iter().enumerate().rev() {
+            node = if path_bit(&key_hash, i) {
+                internal_hash(sibling, &node)
+            } else {
+                internal_hash(&node, sibling)
+            };
+        }
+
+        if &node != root {
+            return Err(ProofError::RootMismatch);
+        }
+        Ok(())
+    }
+
+    /// Checks that the storage key of the contract has the value at the root, or has no value
+    /// if `value` is `None`.
+    pub fn verify_storage(
+        &self,
+        root: &Hash,
+        contract: &AccountId,
+        key: &str,
+        value: Option<&[u8]>,
+    ) -> Result<(), ProofError> {
+        self.verify(root, &storage_state_key(contract, key), value)
+    }
+
+    pub fn try_from_bytes(bytes: Vec<u8>) -> std::io::Result<Self> {
+        borsh::BorshDeserialize::deserialize(&mut bytes.as_slice())
+    }
+
+    pub fn into_bytes(&self) -> Vec<u8> {
+        borsh::BorshSerialize::try_to_vec(&self).expect("Expected to serialize")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// State key whose path starts with the given bit, `true` for the right subtree.
+    fn state_key(name: &str, right: bool) -> Vec<u8> {
+        (0..)
+            .map(|i| format!("{}{}", name, i).into_bytes())
+            .find(|key| path_bit(&sha256(key), 0) == right)
+            .unwrap()
+    }
+
+    fn leaf(key: &[u8], value: &[u8]) -> ProofLeaf {
+        ProofLeaf {
+            key_hash: sha256(key),
+            value_hash: sha256(value),
+        }
+    }
+
+    fn hash(leaf: &ProofLeaf) -> Hash {
+        leaf_hash(&leaf.key_hash, &leaf.value_hash)
+    }
+
+    /// Tree with one leaf under each child of the root.
+    struct Tree {
+        root: Hash,
+        left_key: Vec<u8>,
+        left: ProofLeaf,
+        right: ProofLeaf,
+    }
+
+    fn tree() -> Tree {
+        let left_key = state_key("left", false);
+        let left = leaf(&left_key, b"left value");
+        let right = leaf(&state_key("right", true), b"right value");
+        Tree {
+            root: internal_hash(&hash(&left), &hash(&right)),
+            left_key,
+            left,
+            right,
+        }
+    }
+
+    #[test]
+    fn verifies_present_key() {
+        let tree = tree();
+        let proof = StateProof {
+            siblings: vec![hash(&tree.right)],
+            leaf: Some(tree.left.clone()),
+        };
+
+        assert_eq!(
+            proof.verify(&tree.root, &tree.left_key, Some(b"left value")),
+            Ok(())
+        );
+        assert_eq!(
+            proof.verify(&tree.root, &tree.left_key, Some(b"other value")),
+            Err(ProofError::ValueMismatch)
+        );
+        assert_eq!(
+            proof.verify(&tree.root, &tree.left_key, None),
+            Err(ProofError::ValueMismatch)
+        );
+    }
+
+    #[test]
+    fn verifies_absent_key_ending_at_another_leaf() {
+        let tree = tree();
+        let absent_key = state_key("absent", false);
+        let proof = StateProof {
+            siblings: vec![hash(&tree.right)],
+            leaf: Some(tree.left.clone()),
+        };
+
+        assert_eq!(proof.verify(&tree.root, &absent_key, None), Ok(()));
+        assert_eq!(
+            proof.verify(&tree.root, &absent_key, Some(b"left value")),
+            Err(ProofError::ValueMismatch)
+        );
+    }
+
+    #[test]
+    fn verifies_absent_key_ending_at_empty_subtree() {
+        let left_key = state_key("left", false);
+        let left = leaf(&left_key, b"left value");
+        let root = internal_hash(&hash(&left), &EMPTY_HASH);
+        let proof = StateProof {
+            siblings: vec![hash(&left)],
+            leaf: None,
+        };
+
+        assert_eq!(
+            proof.verify(&root, &state_key("absent", true), None),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_leaf_off_the_key_path() {
+        let tree = tree();
+        let proof = StateProof {
+            siblings: vec![hash(&tree.left)],
+            leaf: Some(tree.right.clone()),
+        };
+
+        assert_eq!(
+            proof.verify(&tree.root, &state_key("absent", false), None),
+            Err(ProofError::InvalidLeaf)
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_sibling() {
+        let tree = tree();
+        let mut sibling = hash(&tree.right);
+        sibling[0] ^= 1;
+        let proof = StateProof {
+            siblings: vec![sibling],
+            leaf: Some(tree.left.clone()),
+        };
+
+        assert_eq!(
+            proof.verify(&tree.root, &tree.left_key, Some(b"left value")),
+            Err(ProofError::RootMismatch)
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_root() {
+        let tree = tree();
+        let proof = StateProof {
+            siblings: vec![hash(&tree.right)],
+            leaf: Some(tree.left.clone()),
+        };
+        let mut root = tree.root;
+        root[31] ^= 1;
+
+        assert_eq!(
+            proof.verify(&root, &tree.left_key, Some(b"left value")),
+            Err(ProofError::RootMismatch)
+        );
+        assert_eq!(
+            proof.verify(&EMPTY_HASH, &tree.left_key, Some(b"left value")),
+            Err(ProofError::RootMismatch)
+        );
+    }
+
+    #[test]
+    fn round_trips_bytes() {
+        let tree = tree();
+        let proof = StateProof {
+            siblings: vec![hash(&tree.right)],
+            leaf: Some(tree.left),
+        };
+
+        assert_eq!(
+            StateProof::try_from_bytes(proof.into_bytes()).unwrap(),
+            proof
+        );
+    }
+}
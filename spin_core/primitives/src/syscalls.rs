@@ -1,5 +1,7 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 
+use crate::{Callback, ContractCall, Schedule};
+
 pub const GET_ENV_CALL: risc0_zkvm_platform::syscall::SyscallName = unsafe {
     risc0_zkvm_platform::syscall::SyscallName::from_bytes_with_nul(
         concat!("spinvm", "::", "GET_ENV", "\0").as_ptr(),
@@ -12,6 +14,47 @@ pub const CROSS_CONTRACT_CALL: risc0_zkvm_platform::syscall::SyscallName = unsaf
     )
 };
 
+/// Like [`CROSS_CONTRACT_CALL`], but a failure of the callee is returned to the caller
+/// as a Borsh-encoded `Result<Vec<u8>, CallError>` instead of failing it.
+pub const TRY_CROSS_CONTRACT_CALL: risc0_zkvm_platform::syscall::SyscallName = unsafe {
+    risc0_zkvm_platform::syscall::SyscallName::from_bytes_with_nul(
+        concat!("spinvm", "::", "TRY_CROSS_CONTRACT_CALL", "\0").as_ptr(),
+    )
+};
+
+/// Returns the response of the last cross-contract call when it didn't fit in the buffer of the
+/// guest. Cross-contract calls return the length of their response in words, so the guest knows
+/// when to fetch it and how large a buffer it needs.
+pub const CALL_RESPONSE: risc0_zkvm_platform::syscall::SyscallName = unsafe {
+    risc0_zkvm_platform::syscall::SyscallName::from_bytes_with_nul(
+        concat!("spinvm", "::", "CALL_RESPONSE", "\0").as_ptr(),
+    )
+};
+
+pub const ASYNC_CROSS_CONTRACT_CALL: risc0_zkvm_platform::syscall::SyscallName = unsafe {
+    risc0_zkvm_platform::syscall::SyscallName::from_bytes_with_nul(
+        concat!("spinvm", "::", "ASYNC_CROSS_CONTRACT_CALL", "\0").as_ptr(),
+    )
+};
+
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct AsyncCallRequest {
+    pub call: ContractCall,
+    pub callback: Option<Callback>,
+}
+
+pub const SCHEDULE_CALL: risc0_zkvm_platform::syscall::SyscallName = unsafe {
+    risc0_zkvm_platform::syscall::SyscallName::from_bytes_with_nul(
+        concat!("spinvm", "::", "SCHEDULE_CALL", "\0").as_ptr(),
+    )
+};
+
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct ScheduleCallRequest {
+    pub call: ContractCall,
+    pub schedule: Schedule,
+}
+
 pub const GET_STORAGE_CALL: risc0_zkvm_platform::syscall::SyscallName = unsafe {
     risc0_zkvm_platform::syscall::SyscallName::from_bytes_with_nul(
         concat!("spinvm", "::", "GET_STORAGE", "\0").as_ptr(),
@@ -37,8 +80,46 @@ pub struct GetStorageResponse {
     pub state: Vec<u8>,
 }
 
+/// Takes the UTF-8 key as input, like [`GET_STORAGE_CALL`].
+pub const REMOVE_STORAGE_CALL: risc0_zkvm_platform::syscall::SyscallName = unsafe {
+    risc0_zkvm_platform::syscall::SyscallName::from_bytes_with_nul(
+        concat!("spinvm", "::", "REMOVE_STORAGE", "\0").as_ptr(),
+    )
+};
+
+pub const ITER_STORAGE_PREFIX_CALL: risc0_zkvm_platform::syscall::SyscallName = unsafe {
+    risc0_zkvm_platform::syscall::SyscallName::from_bytes_with_nul(
+        concat!("spinvm", "::", "ITER_STORAGE_PREFIX", "\0").as_ptr(),
+    )
+};
+
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct IterStoragePrefixRequest {
+    pub prefix: String,
+    /// Key to start from, returned by the previous page.
+    pub cursor: Option<String>,
+    /// Maximum number of entries in the page.
+    pub limit: u32,
+}
+
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct IterStoragePrefixResponse {
+    /// SHA-256 of the Borsh-encoded `entries`.
+    pub hash: [u8; 32],
+    /// Keys and values sorted by key.
+    pub entries: Vec<(String, Vec<u8>)>,
+    /// Key to start the next page from, `None` if this is the last page.
+    pub cursor: Option<String>,
+}
+
 pub const GET_ACCOUNT_MAPPING: risc0_zkvm_platform::syscall::SyscallName = unsafe {
     risc0_zkvm_platform::syscall::SyscallName::from_bytes_with_nul(
         concat!("spinvm", "::", "GET_ACCOUNT_MAPPING", "\0").as_ptr(),
     )
 };
+
+pub const GET_REVERSE_ACCOUNT_MAPPING: risc0_zkvm_platform::syscall::SyscallName = unsafe {
+    risc0_zkvm_platform::syscall::SyscallName::from_bytes_with_nul(
+        concat!("spinvm", "::", "GET_REVERSE_ACCOUNT_MAPPING", "\0").as_ptr(),
+    )
+};
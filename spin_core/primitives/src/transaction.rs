@@ -0,0 +1,127 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use risc0_zkvm::sha::rust_crypto::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
+
+use crate::{AccountId, ContractCall, PublicKey};
+
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq)]
+pub struct Signature(#[serde(with = "hex")] pub [u8; 64]);
+
+/// Key which may sign transactions of an account.
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq)]
+pub struct AccessKey {
+    /// Nonce of the last transaction signed with the key.
+    pub nonce: u64,
+    pub permission: AccessKeyPermission,
+}
+
+impl AccessKey {
+    pub fn full_access() -> Self {
+        Self {
+            nonce: 0,
+            permission: AccessKeyPermission::FullAccess,
+        }
+    }
+
+    pub fn try_from_bytes(bytes: Vec<u8>) -> std::io::Result<Self> {
+        borsh::BorshDeserialize::deserialize(&mut bytes.as_slice())
+    }
+
+    pub fn into_bytes(&self) -> Vec<u8> {
+        borsh::BorshSerialize::try_to_vec(&self).expect("Expected to serialize")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessKeyPermission {
+    FullAccess,
+    FunctionCall(FunctionCallPermission),
+}
+
+/// Permission to call methods of a single contract, e.g. a session key of a game client.
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq)]
+pub struct FunctionCallPermission {
+    pub receiver: AccountId,
+    /// Methods the key may call, any method of the receiver if empty.
+    #[serde(default)]
+    pub method_names: Vec<String>,
+    /// Gas the key may still spend, unlimited if `None`.
+    #[serde(default)]
+    pub allowance: Option<u64>,
+}
+
+impl FunctionCallPermission {
+    pub fn allows(&self, call: &ContractCall) -> bool {
+        call.account == self.receiver
+            && (self.method_names.is_empty()
+                || self.method_names.contains(&call.function_call.method))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Creates a sub-account of the signer, with a full access key if given.
+    CreateAccount {
+        account: AccountId,
+        public_key: Option<PublicKey>,
+    },
+    /// Deploys the code to the signer or to a sub-account created earlier in the transaction.
+    DeployContract {
+        account: AccountId,
+        #[serde(with = "hex")]
+        code: Vec<u8>,
+    },
+    FunctionCall(ContractCall),
+    /// Moves balance of the signer to an existing account.
+    Transfer {
+        receiver: AccountId,
+        amount: u128,
+    },
+    AddKey {
+        public_key: PublicKey,
+        permission: AccessKeyPermission,
+    },
+    DeleteKey {
+        public_key: PublicKey,
+    },
+}
+
+/// Actions executed in order under the same signer; if one fails, all of them are reverted.
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone)]
+pub struct Transaction {
+    pub signer: AccountId,
+    /// Access key of the signer the transaction is signed with.
+    pub public_key: PublicKey,
+    /// Must be greater than the nonce of the access key.
+    pub nonce: u64,
+    pub actions: Vec<Action>,
+}
+
+impl Transaction {
+    /// Gas attached to all function calls of the transaction, `None` if it overflows.
+    pub fn attached_gas(&self) -> Option<u64> {
+        self.actions
+            .iter()
+            .map(|action| match action {
+                Action::FunctionCall(call) => call.attached_gas,
+                _ => 0,
+            })
+            .try_fold(0u64, |total, gas| total.checked_add(gas))
+    }
+
+    /// SHA-256 of the Borsh-encoded transaction, which is what gets signed.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::default();
+        hasher.update(self.try_to_vec().expect("Expected to serialize"));
+        hasher.finalize().into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, BorshSerialize, BorshDeserialize, Clone)]
+pub struct SignedTransaction {
+    pub transaction: Transaction,
+    /// Ed25519 signature of [`Transaction::hash`].
+    pub signature: Signature,
+}
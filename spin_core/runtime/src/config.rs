@@ -0,0 +1,170 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use spin_primitives::AccountId;
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::state::State;
+
+/// Configuration of the runtime, loaded from a TOML file; missing fields take default values.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    pub state: StateBackend,
+    /// Nesting depth of cross-contract calls, the called method being at depth 0.
+    pub max_call_depth: u32,
+    pub gas: GasSchedule,
+    /// Limits of every contract, unless overridden in `contracts`.
+    #[serde(flatten)]
+    pub limits: ContractLimits,
+    /// Limits overridden for some contracts.
+    pub contracts: BTreeMap<AccountId, ContractOverrides>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum StateBackend {
+    /// Sled database in a directory.
+    Sled { path: PathBuf },
+    /// Sled database deleted when the state is dropped, for tests and benchmarks.
+    Temporary,
+}
+
+/// Gas charged for the resources used by a call.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct GasSchedule {
+    /// Gas per cycle of the session, the session is stopped once the attached gas is used up.
+    pub cycle: u64,
+    /// Gas per syscall made by the contract.
+    pub syscall: u64,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct ContractLimits {
+    /// Bytes of guest memory the contract ELF is loaded into.
+    pub memory_limit: u32,
+    /// Bytes of a page of the memory image.
+    pub page_size: u32,
+    /// Bytes of the journal committed by a call.
+    pub max_output_size: usize,
+    /// Segments are limited to `2^segment_limit_po2` cycles, the zkVM default if not set.
+    pub segment_limit_po2: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct ContractOverrides {
+    pub memory_limit: Option<u32>,
+    pub page_size: Option<u32>,
+    pub max_output_size: Option<usize>,
+    pub segment_limit_po2: Option<u32>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            state: StateBackend::default(),
+            max_call_depth: 16,
+            gas: GasSchedule::default(),
+            limits: ContractLimits::default(),
+            contracts: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for StateBackend {
+    fn default() -> Self {
+        StateBackend::Sled {
+            path: PathBuf::from("./state"),
+        }
+    }
+}
+
+impl Default for GasSchedule {
+    fn default() -> Self {
+        Self {
+            cycle: 1,
+            syscall: 0,
+        }
+    }
+}
+
+impl Default for ContractLimits {
+    fn default() -> Self {
+        Self {
+            memory_limit: 0x10000000,
+            page_size: 0x400,
+            max_output_size: 0x100000,
+            segment_limit_po2: None,
+        }
+    }
+}
+
+impl RuntimeConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("Can't read runtime config {:?}", path))?;
+        let config: Self = toml::from_str(&file)
+            .with_context(|| format!("Can't parse runtime config {:?}", path))?;
+
+        if config.gas.cycle == 0 {
+            bail!("Gas per cycle must be positive");
+        }
+        Ok(config)
+    }
+
+    /// Loads the config file, or takes the default config without one, and keeps the state in a
+    /// Sled database in `state_dir` if set.
+    pub fn resolve(path: Option<&Path>, state_dir: Option<&Path>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => Self::load(path)?,
+            None => Self::default(),
+        };
+        if let Some(state_dir) = state_dir {
+            config.state = StateBackend::Sled {
+                path: state_dir.to_path_buf(),
+            };
+        }
+        Ok(config)
+    }
+
+    /// Limits of the contract, with its overrides applied.
+    pub fn limits(&self, contract: &AccountId) -> ContractLimits {
+        let limits = self.limits;
+        match self.contracts.get(contract) {
+            Some(overrides) => ContractLimits {
+                memory_limit: overrides.memory_limit.unwrap_or(limits.memory_limit),
+                page_size: overrides.page_size.unwrap_or(limits.page_size),
+                max_output_size: overrides.max_output_size.unwrap_or(limits.max_output_size),
+                segment_limit_po2: overrides.segment_limit_po2.or(limits.segment_limit_po2),
+            },
+            None => limits,
+        }
+    }
+}
+
+impl StateBackend {
+    pub fn open(&self) -> Result<State> {
+        match self {
+            StateBackend::Sled { path } => State::open(path),
+            StateBackend::Temporary => State::open_temporary(),
+        }
+    }
+}
+
+impl GasSchedule {
+    /// Cycles a call can run with the attached gas.
+    pub fn cycle_limit(&self, attached_gas: u64) -> u64 {
+        attached_gas / self.cycle
+    }
+
+    pub fn gas(&self, cycles: u64, syscalls: u64) -> u64 {
+        cycles
+            .saturating_mul(self.cycle)
+            .saturating_add(syscalls.saturating_mul(self.syscall))
+    }
+}
@@ -1,27 +1,83 @@
-use anyhow::Result;
-use risc0_zkvm::Session;
+use anyhow::{bail, Result};
+use risc0_zkvm::{sha::Digest, Session};
 
 use std::{
-    str::FromStr,
+    collections::BTreeMap,
     sync::{Arc, RwLock},
+    time::Duration,
 };
 
-use spin_primitives::{AccountId, CallEnv, ContractCall, FunctionCall};
+use spin_primitives::{
+    AccountId, CallEnv, CallError, Callback, ContractCall, FunctionCall, Schedule,
+};
+
+use crate::{
+    config::RuntimeConfig,
+    state::{receipts::Receipt, schedule::ScheduledCall, State, EVM_CONTRACT},
+};
+
+/// Resources used by the execution of a call.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionStats {
+    /// Cycles of the session, every segment padded to a power of two.
+    pub cycles: u64,
+    pub segments: u64,
+    /// Number of calls of every syscall, by name.
+    pub syscalls: BTreeMap<String, u64>,
+    /// Bytes of the storage keys and values read by the contract.
+    pub storage_bytes_read: u64,
+    /// Bytes of the storage keys and values written, and of the keys removed, by the contract.
+    pub storage_bytes_written: u64,
+    /// Wall time of the execution, which includes the cross-contract calls.
+    pub execution_time: Duration,
+}
+
+impl ExecutionStats {
+    /// Adds the resources used by a cross-contract call, whose time is already included.
+    fn add_call(&mut self, call: &ExecutionStats) {
+        self.cycles += call.cycles;
+        self.segments += call.segments;
+        for (syscall, count) in call.syscalls.iter() {
+            *self.syscalls.entry(syscall.clone()).or_default() += count;
+        }
+        self.storage_bytes_read += call.storage_bytes_read;
+        self.storage_bytes_written += call.storage_bytes_written;
+    }
+}
 
 pub struct ExecutionContext {
+    config: Arc<RuntimeConfig>,
+    state: Arc<RwLock<State>>,
     signer: AccountId,
     caller: AccountId,
     contract: AccountId,
     attached_gas: u64,
     used_gas: u64,
     call: FunctionCall,
+    /// Nesting depth of the call, 0 for a call which isn't a cross-contract call.
+    depth: u32,
 
     cross_contract_calls: Vec<Arc<RwLock<ExecutionContext>>>,
+    /// Asynchronous calls scheduled by this call.
+    receipts: Vec<Receipt>,
+    /// Calls scheduled by this call to run at future blocks.
+    scheduled_calls: Vec<ScheduledCall>,
+    /// Attached gas of the asynchronous and scheduled calls, charged to this call.
+    prepaid_gas: u64,
+    /// Response of the last cross-contract call which didn't fit in the buffer of the guest.
+    call_response: Option<Vec<u32>>,
     session: Option<Session>,
+    /// Image ID of the executed contract code.
+    image_id: Option<Digest>,
+    /// Set when the call failed and its state changes were reverted.
+    error: Option<CallError>,
+    stats: ExecutionStats,
 }
 
 impl ExecutionContext {
     pub fn new(
+        config: Arc<RuntimeConfig>,
+        state: Arc<RwLock<State>>,
         signer: AccountId,
         caller: AccountId,
         contract: AccountId,
@@ -29,38 +85,152 @@ impl ExecutionContext {
         call: FunctionCall,
     ) -> Self {
         Self {
+            config,
+            state,
             signer,
             caller,
             contract,
             attached_gas,
             used_gas: 0,
             call,
+            depth: 0,
             cross_contract_calls: Vec::new(),
+            receipts: Vec::new(),
+            scheduled_calls: Vec::new(),
+            prepaid_gas: 0,
+            call_response: None,
             session: None,
+            image_id: None,
+            error: None,
+            stats: ExecutionStats::default(),
         }
     }
 
+    /// Starts a cross-contract call, which is recorded as failed if this call doesn't have its
+    /// attached gas left or if it would be nested too deep.
     pub fn cross_contract_call(
         &mut self,
         call: ContractCall,
     ) -> Result<Arc<RwLock<ExecutionContext>>> {
-        if self.available_gas() < call.attached_gas {
-            return Err(anyhow::anyhow!("Not enough gas"));
-        }
+        let depth = self.depth + 1;
+        let error = if depth > self.config.max_call_depth {
+            Some(CallError::CallDepthExceeded)
+        } else if self.available_gas() < call.attached_gas {
+            Some(CallError::OutOfGas)
+        } else {
+            None
+        };
         let context = Arc::new(RwLock::new(ExecutionContext {
+            config: self.config.clone(),
+            state: self.state.clone(),
             signer: self.signer().clone(),
             caller: self.contract().clone(),
             contract: call.account.clone(),
             attached_gas: call.attached_gas,
             used_gas: 0,
             call: call.function_call,
+            depth,
             cross_contract_calls: Vec::new(),
+            receipts: Vec::new(),
+            scheduled_calls: Vec::new(),
+            prepaid_gas: 0,
+            call_response: None,
             session: None,
+            image_id: None,
+            error: error.clone(),
+            stats: ExecutionStats::default(),
         }));
 
         self.cross_contract_calls.push(context.clone());
 
-        Ok(context)
+        match error {
+            Some(error) => Err(error.into()),
+            None => Ok(context),
+        }
+    }
+
+    /// Schedules an asynchronous call, executed after this execution is committed, prepaying the
+    /// attached gas of the call and of its callback.
+    pub fn schedule_call(&mut self, call: ContractCall, callback: Option<Callback>) -> Result<()> {
+        let gas = callback
+            .as_ref()
+            .map_or(Some(call.attached_gas), |callback| {
+                call.attached_gas.checked_add(callback.attached_gas)
+            })
+            .ok_or(CallError::OutOfGas)?;
+        if self.available_gas() < gas {
+            return Err(CallError::OutOfGas.into());
+        }
+        self.prepaid_gas += gas;
+
+        self.receipts.push(Receipt {
+            signer: self.signer.clone(),
+            caller: self.contract.clone(),
+            call,
+            callback,
+        });
+        Ok(())
+    }
+
+    /// Schedules a call to run at future blocks, prepaying the attached gas of all of its runs.
+    pub fn schedule_at(&mut self, call: ContractCall, schedule: Schedule) -> Result<()> {
+        if schedule.runs == 0 {
+            bail!("Scheduled call must run at least once");
+        }
+        if schedule.runs > 1 && !matches!(schedule.interval, Some(interval) if interval > 0) {
+            bail!("Recurring call must have a positive interval");
+        }
+
+        let gas = call
+            .attached_gas
+            .checked_mul(schedule.runs.into())
+            .ok_or(CallError::OutOfGas)?;
+        if self.available_gas() < gas {
+            return Err(CallError::OutOfGas.into());
+        }
+        self.prepaid_gas += gas;
+
+        self.scheduled_calls.push(ScheduledCall {
+            signer: self.signer.clone(),
+            caller: self.contract.clone(),
+            call,
+            schedule,
+        });
+        Ok(())
+    }
+
+    /// Calls scheduled by this call and all of its cross-contract calls, in order.
+    pub fn scheduled_calls(&self) -> Vec<ScheduledCall> {
+        if self.failed() {
+            return Vec::new();
+        }
+
+        let mut scheduled_calls = self.scheduled_calls.clone();
+        for call in self.cross_contract_calls.iter() {
+            scheduled_calls.extend(call.read().unwrap().scheduled_calls());
+        }
+        scheduled_calls
+    }
+
+    /// Asynchronous calls scheduled by this call and all of its cross-contract calls, in order.
+    pub fn receipts(&self) -> Vec<Receipt> {
+        if self.failed() {
+            return Vec::new();
+        }
+
+        let mut receipts = self.receipts.clone();
+        for call in self.cross_contract_calls.iter() {
+            receipts.extend(call.read().unwrap().receipts());
+        }
+        receipts
+    }
+
+    pub fn config(&self) -> &Arc<RuntimeConfig> {
+        &self.config
+    }
+
+    pub fn state(&self) -> &Arc<RwLock<State>> {
+        &self.state
     }
 
     pub fn signer(&self) -> &AccountId {
@@ -79,6 +249,16 @@ impl ExecutionContext {
         self.attached_gas
     }
 
+    /// Account whose balance is locked for the storage this call writes: the contract, except
+    /// for the EVM meta contract, which charges the storage of EVM accounts to its caller.
+    pub fn storage_payer(&self) -> &AccountId {
+        if self.contract.as_str() == EVM_CONTRACT {
+            &self.caller
+        } else {
+            &self.contract
+        }
+    }
+
     pub fn used_gas(&self) -> u64 {
         self.used_gas
     }
@@ -91,21 +271,45 @@ impl ExecutionContext {
         let cc_gas = self
             .cross_contract_calls
             .iter()
-            .map(|call| call.read().unwrap().used_gas())
+            .map(|call| call.read().unwrap().total_used_gas())
             .sum::<u64>();
 
         self.attached_gas
             .saturating_sub(self.used_gas)
+            .saturating_sub(self.prepaid_gas)
             .saturating_sub(cc_gas)
     }
 
-    pub fn call_env(&self) -> CallEnv {
-        CallEnv {
+    /// Gas used by this call and all of its cross-contract calls, including prepaid gas.
+    pub fn total_used_gas(&self) -> u64 {
+        if self.failed() {
+            return self.used_gas;
+        }
+
+        let cc_gas = self
+            .cross_contract_calls
+            .iter()
+            .map(|call| call.read().unwrap().total_used_gas())
+            .sum::<u64>();
+
+        self.used_gas
+            .saturating_add(self.prepaid_gas)
+            .saturating_add(cc_gas)
+    }
+
+    pub fn call_env(&self) -> Result<CallEnv> {
+        Ok(CallEnv {
             signer: self.signer().clone(),
             caller: self.caller().clone(),
             contract: self.contract().clone(),
             attached_gas: self.attached_gas(),
-        }
+            block: self.state.read().unwrap().block()?,
+        })
+    }
+
+    /// Cross-contract calls made by this call, in order, including the failed ones.
+    pub fn cross_contract_calls(&self) -> &[Arc<RwLock<ExecutionContext>>] {
+        &self.cross_contract_calls
     }
 
     pub fn execution_session(&self) -> Option<&Session> {
@@ -116,34 +320,142 @@ impl ExecutionContext {
         self.session = Some(session);
     }
 
-    pub fn set_gas_usage(&mut self, used_gas: u64) {
-        self.used_gas = used_gas;
-    }
-
-    // TODO: remove hardcode, use custom alias system
-    pub fn get_account_evm_address(account_id: AccountId) -> eth_primitive_types::H160 {
-        let mut hardcoded_mappings = std::collections::HashMap::new();
-        hardcoded_mappings.insert(
-            AccountId::from(AccountId::new("alice.spin".to_string())),
-            eth_primitive_types::H160::from_str("0x0FF1CE0000000000000000000000000000000001")
-                .unwrap(),
-        );
-        hardcoded_mappings.insert(
-            AccountId::from(AccountId::new("bob.spin".to_string())),
-            eth_primitive_types::H160::from_str("0x0FF1CE0000000000000000000000000000000002")
-                .unwrap(),
-        );
-        hardcoded_mappings.insert(
-            AccountId::from(AccountId::new("charlie.spin".to_string())),
-            eth_primitive_types::H160::from_str("0x0FF1CE0000000000000000000000000000000003")
-                .unwrap(),
-        );
-        hardcoded_mappings.insert(
-            AccountId::from(AccountId::new("eve.spin".to_string())),
-            eth_primitive_types::H160::from_str("0x0FF1CE0000000000000000000000000000000004")
-                .unwrap(),
-        );
-
-        hardcoded_mappings.get(&account_id).unwrap().clone()
+    /// Keeps the response of a cross-contract call until the guest fetches it.
+    pub fn set_call_response(&mut self, response: Vec<u32>) {
+        self.call_response = Some(response);
+    }
+
+    pub fn take_call_response(&mut self) -> Option<Vec<u32>> {
+        self.call_response.take()
+    }
+
+    pub fn image_id(&self) -> Option<Digest> {
+        self.image_id
+    }
+
+    pub fn set_image_id(&mut self, image_id: Digest) {
+        self.image_id = Some(image_id);
+    }
+
+    /// Charges the gas of the cycles run so far and of the syscalls made so far, failing the call
+    /// if, with its prepaid gas and the gas of its cross-contract calls, it is more than the
+    /// attached gas.
+    pub fn charge_gas(&mut self, cycles: u64) -> Result<()> {
+        let syscalls = self.stats.syscalls.values().sum();
+        self.used_gas = self.config.gas.gas(cycles, syscalls);
+        if self.total_used_gas() > self.attached_gas {
+            return Err(CallError::OutOfGas.into());
+        }
+        Ok(())
+    }
+
+    /// Resources used by this call, without its cross-contract calls.
+    pub fn stats(&self) -> &ExecutionStats {
+        &self.stats
+    }
+
+    /// Resources used by this call and all of its cross-contract calls.
+    pub fn total_stats(&self) -> ExecutionStats {
+        let mut stats = self.stats.clone();
+        for call in self.cross_contract_calls.iter() {
+            stats.add_call(&call.read().unwrap().total_stats());
+        }
+        stats
+    }
+
+    pub fn set_session_stats(&mut self, cycles: u64, segments: u64, execution_time: Duration) {
+        self.stats.cycles = cycles;
+        self.stats.segments = segments;
+        self.stats.execution_time = execution_time;
+    }
+
+    pub fn record_syscall(&mut self, syscall: &str) {
+        *self.stats.syscalls.entry(syscall.to_string()).or_default() += 1;
+    }
+
+    pub fn record_storage_read(&mut self, bytes: usize) {
+        self.stats.storage_bytes_read += bytes as u64;
+    }
+
+    pub fn record_storage_write(&mut self, bytes: usize) {
+        self.stats.storage_bytes_written += bytes as u64;
+    }
+
+    pub fn failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Error the call failed with, returned to its caller.
+    pub fn error(&self) -> Option<&CallError> {
+        self.error.as_ref()
+    }
+
+    /// Marks the call as failed, charging all of its attached gas.
+    pub fn set_failed(&mut self, error: CallError) {
+        self.error = Some(error);
+        self.used_gas = self.attached_gas;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(attached_gas: u64) -> ExecutionContext {
+        let signer = AccountId::new(String::from("alice.spin"));
+        ExecutionContext::new(
+            Arc::new(RuntimeConfig::default()),
+            Arc::new(RwLock::new(State::open_temporary().unwrap())),
+            signer.clone(),
+            signer,
+            AccountId::new(String::from("caller.spin")),
+            attached_gas,
+            FunctionCall::new(String::from("run"), ()),
+        )
+    }
+
+    fn call(attached_gas: u64) -> ContractCall {
+        ContractCall::new(
+            AccountId::new(String::from("callee.spin")),
+            String::from("run"),
+            (),
+            attached_gas,
+        )
+    }
+
+    fn is_out_of_gas(error: anyhow::Error) -> bool {
+        matches!(error.downcast_ref::<CallError>(), Some(CallError::OutOfGas))
+    }
+
+    #[test]
+    fn caller_computing_after_cross_contract_call_runs_out_of_gas() {
+        let mut caller = context(1000);
+        caller.charge_gas(100).unwrap();
+
+        let callee = caller.cross_contract_call(call(800)).unwrap();
+        callee.write().unwrap().charge_gas(700).unwrap();
+        assert_eq!(caller.available_gas(), 200);
+
+        caller.charge_gas(300).unwrap();
+        assert!(is_out_of_gas(caller.charge_gas(301).unwrap_err()));
+    }
+
+    #[test]
+    fn prepaid_gas_counts_against_attached_gas() {
+        let mut caller = context(1000);
+        caller.schedule_call(call(600), None).unwrap();
+
+        caller.charge_gas(400).unwrap();
+        assert!(is_out_of_gas(caller.charge_gas(401).unwrap_err()));
+    }
+
+    #[test]
+    fn callee_is_bound_by_its_attached_gas() {
+        let mut caller = context(1000);
+        let callee = caller.cross_contract_call(call(500)).unwrap();
+
+        assert!(is_out_of_gas(
+            callee.write().unwrap().charge_gas(501).unwrap_err()
+        ));
     }
 }
@@ -1,32 +1,39 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
+};
 
-use anyhow::{Context, Result};
-use risc0_zkvm::{serde::to_vec, Executor, ExecutorEnv};
+use anyhow::{anyhow, bail, Context, Result};
+use risc0_zkvm::{serde::to_vec, Executor, ExecutorEnv, Syscall, SyscallContext};
 use spin_primitives::{
     syscalls::{
-        CROSS_CONTRACT_CALL, GET_ACCOUNT_MAPPING, GET_ENV_CALL, GET_STORAGE_CALL, SET_STORAGE_CALL,
+        ASYNC_CROSS_CONTRACT_CALL, CALL_RESPONSE, CROSS_CONTRACT_CALL, GET_ACCOUNT_MAPPING,
+        GET_ENV_CALL, GET_REVERSE_ACCOUNT_MAPPING, GET_STORAGE_CALL, ITER_STORAGE_PREFIX_CALL,
+        REMOVE_STORAGE_CALL, SCHEDULE_CALL, SET_STORAGE_CALL, TRY_CROSS_CONTRACT_CALL,
     },
-    AccountId,
+    CallError, ExecutionCommittment, UNKNOWN_METHOD_PANIC,
 };
-use tracing::debug;
+use spin_verifier::{CallProof, ProvenCall};
+use tracing::{debug, warn};
 
 use crate::syscalls::{
-    accounts_mapping::AccountsMappingHandler, cross_contract::CrossContractCallHandler,
+    accounts_mapping::{AccountsMappingHandler, ReverseAccountsMappingHandler},
+    async_call::AsyncCallHandler,
+    cross_contract::{CallResponseHandler, CrossContractCallHandler},
     env::GetEnvCallHandler,
+    schedule::ScheduleCallHandler,
 };
 use crate::{
     context::ExecutionContext,
-    syscalls::storage::{GetStorageCallHandler, SetStorageCallHandler},
+    profiler::{Profiler, Symbols},
+    prover::Prover,
+    state::EVM_CONTRACT,
+    syscalls::storage::{
+        GetStorageCallHandler, IterStoragePrefixCallHandler, RemoveStorageCallHandler,
+        SetStorageCallHandler,
+    },
 };
 
-const MAX_MEMORY: u32 = 0x10000000;
-const PAGE_SIZE: u32 = 0x400;
-
-fn load_contract(account: AccountId) -> Result<Vec<u8>> {
-    std::fs::read(format!("./state/contracts/{}", &account.to_string()))
-        .with_context(|| format!("Can't read contract {}", account.to_string()))
-}
-
 struct ContractLogger {
     context: Arc<RwLock<ExecutionContext>>,
 }
@@ -54,64 +61,294 @@ impl std::io::Write for ContractLogger {
     }
 }
 
+/// Counts the calls of a syscall in the stats of the context and charges the gas used so far, so
+/// that the handler sees the gas left to the call.
+struct CountedSyscall<S> {
+    context: Arc<RwLock<ExecutionContext>>,
+    handler: S,
+}
+
+impl<S: Syscall> CountedSyscall<S> {
+    fn new(context: &Arc<RwLock<ExecutionContext>>, handler: S) -> Self {
+        Self {
+            context: context.clone(),
+            handler,
+        }
+    }
+}
+
+impl<S: Syscall> Syscall for CountedSyscall<S> {
+    fn syscall(
+        &mut self,
+        syscall: &str,
+        ctx: &mut dyn SyscallContext,
+        to_guest: &mut [u32],
+    ) -> Result<(u32, u32)> {
+        {
+            let mut context = self.context.write().unwrap();
+            context.record_syscall(syscall);
+            context.charge_gas(ctx.get_cycle().try_into()?)?;
+        }
+        self.handler.syscall(syscall, ctx, to_guest)
+    }
+}
+
 pub fn execute(context: Arc<RwLock<ExecutionContext>>) -> Result<risc0_zkvm::Session> {
-    let mut exec = {
+    let (mut exec, image_id, limits, profile) = {
         let ctx = context.read().unwrap();
         debug!(contract = ?ctx.contract(), "Executing contract");
+        let limits = ctx.config().limits(ctx.contract());
+        let cycle_limit = ctx.config().gas.cycle_limit(ctx.attached_gas());
 
-        let env = ExecutorEnv::builder()
-            .add_input(&to_vec(&ctx.call().into_bytes())?)
-            .session_limit(Some(ctx.attached_gas().try_into().unwrap()))
-            .syscall(GET_ENV_CALL, GetEnvCallHandler::new(context.clone()))
+        let mut env = ExecutorEnv::builder();
+        env.add_input(&to_vec(&ctx.call().into_bytes())?)
+            .session_limit(Some(cycle_limit.try_into().unwrap()))
+            .syscall(
+                GET_ENV_CALL,
+                CountedSyscall::new(&context, GetEnvCallHandler::new(context.clone())),
+            )
             .syscall(
                 CROSS_CONTRACT_CALL,
-                CrossContractCallHandler::new(context.clone()),
+                CountedSyscall::new(&context, CrossContractCallHandler::new(context.clone())),
+            )
+            .syscall(
+                TRY_CROSS_CONTRACT_CALL,
+                CountedSyscall::new(
+                    &context,
+                    CrossContractCallHandler::new_fallible(context.clone()),
+                ),
+            )
+            .syscall(
+                CALL_RESPONSE,
+                CountedSyscall::new(&context, CallResponseHandler::new(context.clone())),
+            )
+            .syscall(
+                ASYNC_CROSS_CONTRACT_CALL,
+                CountedSyscall::new(&context, AsyncCallHandler::new(context.clone())),
+            )
+            .syscall(
+                SCHEDULE_CALL,
+                CountedSyscall::new(&context, ScheduleCallHandler::new(context.clone())),
             )
             .syscall(
                 GET_STORAGE_CALL,
-                GetStorageCallHandler::new(context.clone()),
+                CountedSyscall::new(&context, GetStorageCallHandler::new(context.clone())),
             )
             .syscall(
                 SET_STORAGE_CALL,
-                SetStorageCallHandler::new(context.clone()),
+                CountedSyscall::new(&context, SetStorageCallHandler::new(context.clone())),
+            )
+            .syscall(
+                REMOVE_STORAGE_CALL,
+                CountedSyscall::new(&context, RemoveStorageCallHandler::new(context.clone())),
+            )
+            .syscall(
+                ITER_STORAGE_PREFIX_CALL,
+                CountedSyscall::new(&context, IterStoragePrefixCallHandler::new(context.clone())),
             )
             .syscall(
                 GET_ACCOUNT_MAPPING,
-                AccountsMappingHandler::new(context.clone()),
+                CountedSyscall::new(&context, AccountsMappingHandler::new(context.clone())),
             )
-            .stdout(ContractLogger::new(context.clone()))
-            .build()?;
+            .syscall(
+                GET_REVERSE_ACCOUNT_MAPPING,
+                CountedSyscall::new(
+                    &context,
+                    ReverseAccountsMappingHandler::new(context.clone()),
+                ),
+            )
+            .stdout(ContractLogger::new(context.clone()));
+        if let Some(po2) = limits.segment_limit_po2 {
+            env.segment_limit_po2(po2);
+        }
 
-        let elf = if ctx.contract() == &AccountId::new(String::from("evm")) {
+        let elf = if ctx.contract().as_str() == EVM_CONTRACT {
             meta_contracts::EVM_METACONTRACT_ELF.to_vec()
         } else {
-            load_contract(ctx.contract().clone())
-                .context(format!("Load contract {:?}", ctx.contract()))?
+            ctx.state()
+                .read()
+                .unwrap()
+                .load_contract(ctx.contract())
+                .context(CallError::ContractNotFound(ctx.contract().clone()))?
         };
 
-        let program = risc0_zkvm::Program::load_elf(&elf, MAX_MEMORY)?;
-        let image = risc0_zkvm::MemoryImage::new(&program, PAGE_SIZE)?;
-        risc0_zkvm::LocalExecutor::new(env, image, program.entry)
+        let profile = match Profiler::profile_dir() {
+            Some(dir) => {
+                let profiler = Arc::new(Mutex::new(Profiler::new(Symbols::load(&elf)?)));
+                let tracer = profiler.clone();
+                env.trace_callback(move |event| tracer.lock().unwrap().trace(event));
+                let path = Profiler::next_path(&dir, ctx.contract().as_str(), &ctx.call().method);
+                Some((profiler, path))
+            }
+            None => None,
+        };
+
+        let program = risc0_zkvm::Program::load_elf(&elf, limits.memory_limit)?;
+        let image = risc0_zkvm::MemoryImage::new(&program, limits.page_size)?;
+        let image_id = image.compute_id();
+        (
+            risc0_zkvm::LocalExecutor::new(env.build()?, image, program.entry),
+            image_id,
+            limits,
+            profile,
+        )
     };
+    context.write().unwrap().set_image_id(image_id);
 
-    let session = exec.run()?;
+    let started = Instant::now();
+    let session = exec.run();
+    let execution_time = started.elapsed();
+    if let Some((profiler, path)) = profile {
+        match profiler.lock().unwrap().write(&path) {
+            Ok(()) => debug!(?path, "Profile written"),
+            Err(e) => warn!(?path, error = ?e, "Can't write profile"),
+        }
+    }
+    let session = session?;
     {
-        let cycles = 2u64.pow(
-            session
-                .segments
-                .iter()
-                .map(|s| s.resolve().unwrap().po2)
-                .sum::<usize>()
-                .try_into()
-                .unwrap(),
-        );
+        let segments = session
+            .segments
+            .iter()
+            .map(|s| s.resolve().map(|s| s.po2))
+            .collect::<Result<Vec<_>>>()?;
+        // The stats report the cycles the gas is charged for.
+        let cycles = segments.iter().map(|po2| 1u64 << po2).sum();
         let mut ctx = context.write().unwrap();
-        ctx.set_gas_usage(cycles);
+        ctx.set_session_stats(cycles, segments.len() as u64, execution_time);
+        ctx.charge_gas(cycles)?;
+    }
+    if session.journal.len() > limits.max_output_size {
+        bail!(
+            "Output of the call to {} is {} bytes, the limit is {}",
+            context.read().unwrap().contract(),
+            session.journal.len(),
+            limits.max_output_size
+        );
     }
+    verify_committment(&context.read().unwrap(), &session.journal)?;
 
-    // debug!("Start proving...");
-    // let _receipt = session.prove();
-    // debug!("Proved");
+    // The session of a call the contract failed is kept, its receipt proves the failure.
+    let error = (!session.journal.is_empty())
+        .then(|| ExecutionCommittment::try_from_bytes(session.journal.clone()))
+        .transpose()?
+        .and_then(|committment| committment.error);
+    if let Some(error) = error {
+        context.write().unwrap().set_execution_session(session);
+        return Err(error.into());
+    }
 
     Ok(session)
 }
+
+/// Checks that the call committed to its environment and function call, and that the cross-contract
+/// calls hashes it committed match the calls it made, in order and count.
+fn verify_committment(context: &ExecutionContext, journal: &[u8]) -> Result<()> {
+    if journal.is_empty() {
+        return Ok(());
+    }
+
+    let committment = ExecutionCommittment::try_from_bytes(journal.to_vec())
+        .with_context(|| format!("Invalid committment of the call to {}", context.contract()))?;
+    if !committment.is_bound_to(&context.call_env()?, context.call()) {
+        bail!(
+            "Committment of the call to {} doesn't match the call",
+            context.contract()
+        );
+    }
+
+    let committed = committment.cross_calls_hashes;
+    let made = context
+        .cross_contract_calls()
+        .iter()
+        .map(|call| {
+            let call = call.read().unwrap();
+            let result = match (call.error(), call.execution_session()) {
+                (Some(error), _) => Err(error),
+                (None, Some(session)) => Ok(session.journal.as_slice()),
+                (None, None) => bail!("Cross-contract call to {} wasn't executed", call.contract()),
+            };
+            Ok(ExecutionCommittment::cross_call_hash(result))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if committed.len() != made.len() {
+        bail!(
+            "Call to {} committed to {} cross-contract calls, but made {}",
+            context.contract(),
+            committed.len(),
+            made.len()
+        );
+    }
+    if let Some(index) = committed.iter().zip(made.iter()).position(|(c, m)| c != m) {
+        bail!(
+            "Cross-contract call {} of the call to {} doesn't match its committed hash",
+            index,
+            context.contract()
+        );
+    }
+    Ok(())
+}
+
+/// Proves the executed call and all of its cross-contract calls.
+///
+/// The session of the call must be set in the context, sessions of its cross-contract calls are
+/// set when they are executed.
+pub fn prove(context: &ExecutionContext, prover: &dyn Prover) -> Result<ProvenCall> {
+    let session = context
+        .execution_session()
+        .ok_or_else(|| anyhow!("Call to {} wasn't executed", context.contract()))?;
+    let image_id = context
+        .image_id()
+        .ok_or_else(|| anyhow!("Call to {} has no image ID", context.contract()))?;
+
+    debug!(contract = ?context.contract(), "Proving call");
+    let receipt = prover.prove(image_id, session)?;
+    debug!(contract = ?context.contract(), "Call proved");
+
+    let calls = context
+        .cross_contract_calls()
+        .iter()
+        .map(|call| {
+            let call = call.read().unwrap();
+            match (call.error(), call.execution_session()) {
+                (None, _) => prove(&call, prover).map(CallProof::Proven),
+                // Only failures committed by the contract have a receipt, the callers of the
+                // others fail with them.
+                (Some(_), Some(_)) => prove(&call, prover).map(CallProof::Failed),
+                (Some(error), None) => bail!(
+                    "Failure of the call to {} can't be proven: {}",
+                    call.contract(),
+                    error
+                ),
+            }
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(ProvenCall {
+        env: context.call_env()?,
+        call: context.call().clone(),
+        image_id,
+        receipt,
+        calls,
+    })
+}
+
+/// Classifies an execution failure of a called contract.
+pub fn call_error(error: &anyhow::Error) -> CallError {
+    if let Some(call_error) = error.downcast_ref::<CallError>() {
+        return call_error.clone();
+    }
+
+    let message = error.to_string();
+    if message.contains("Session limit exceeded") {
+        return CallError::OutOfGas;
+    }
+
+    match message.strip_prefix("Guest panicked: ") {
+        Some(panic) => match panic.strip_prefix(UNKNOWN_METHOD_PANIC) {
+            Some(method) => CallError::MethodNotFound(method.trim_start_matches(": ").to_string()),
+            None => CallError::Panic(panic.to_string()),
+        },
+        None => CallError::Other(format!("{:#}", error)),
+    }
+}
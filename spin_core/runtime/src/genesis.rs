@@ -0,0 +1,310 @@
+use anyhow::{bail, Context, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use eth_primitive_types::{H160, H256, U256};
+use serde::{Deserialize, Deserializer, Serialize};
+use spin_primitives::{evm::EvmAccount, AccessKey, Account, AccountId, FunctionCall, PublicKey};
+use tracing::info;
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    config::RuntimeConfig,
+    context::ExecutionContext,
+    executor,
+    state::{
+        merkle::{Hash, EMPTY_HASH},
+        State, StateKey, EVM_CONTRACT,
+    },
+};
+
+const DEFAULT_INIT_GAS: u64 = 100_000_000;
+
+/// Initial state of the chain, loaded from a TOML file.
+///
+/// ```toml
+/// [chain]
+/// chain_id = "spin-devnet"
+/// block_time_ms = 1000
+/// storage_byte_cost = "10000000000000000000"
+///
+/// [[accounts]]
+/// id = "alice.spin"
+/// balance = "1000000000000000000000000"
+/// evm_address = "0x0ff1ce0000000000000000000000000000000001"
+/// public_keys = ["1d84d8281f00d160aa6346218af464ed11e8922ccc29e724256d07801c4d7a45"]
+///
+/// [[contracts]]
+/// account = "token.spin"
+/// code = "contracts/token_contract"
+/// balance = "1000000000000000000000000"
+///
+/// [[contracts.init]]
+/// method = "init"
+/// signer = "alice.spin"
+/// args = "0400000053504..."
+///
+/// [[evm_accounts]]
+/// address = "0x0ff1ce0000000000000000000000000000000001"
+/// balance = "0x84595161401484a000000"
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct Genesis {
+    pub chain: ChainConfig,
+    #[serde(default)]
+    pub accounts: Vec<GenesisAccount>,
+    #[serde(default)]
+    pub contracts: Vec<GenesisContract>,
+    #[serde(default)]
+    pub evm_accounts: Vec<GenesisEvmAccount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
+pub struct ChainConfig {
+    pub chain_id: String,
+    pub block_time_ms: u64,
+    /// Balance locked per byte of contract storage, storage is free if zero.
+    #[serde(default, deserialize_with = "deserialize_balance")]
+    pub storage_byte_cost: u128,
+    /// Production chains refuse to run in dev mode, whose receipts are fake.
+    #[serde(default)]
+    pub production: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenesisAccount {
+    pub id: AccountId,
+    #[serde(deserialize_with = "deserialize_balance")]
+    pub balance: u128,
+    /// Full access keys of the account.
+    #[serde(default)]
+    pub public_keys: Vec<PublicKey>,
+    /// EVM alias of the account, derived from the id if not set.
+    #[serde(default)]
+    pub evm_address: Option<H160>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenesisContract {
+    pub account: AccountId,
+    /// Balance of the contract account if it isn't declared in `accounts`, it pays for the
+    /// storage written by init calls.
+    #[serde(default, deserialize_with = "deserialize_balance")]
+    pub balance: u128,
+    /// Path to the contract ELF, relative to the genesis file.
+    pub code: PathBuf,
+    /// Calls executed right after the deployment, in order.
+    #[serde(default)]
+    pub init: Vec<GenesisCall>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenesisCall {
+    pub method: String,
+    /// Defaults to the contract account.
+    pub signer: Option<AccountId>,
+    /// Borsh-encoded arguments as hex.
+    #[serde(default, with = "hex")]
+    pub args: Vec<u8>,
+    #[serde(default = "default_init_gas")]
+    pub attached_gas: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenesisEvmAccount {
+    pub address: H160,
+    #[serde(default)]
+    pub balance: U256,
+    #[serde(default)]
+    pub nonce: U256,
+    /// Contract bytecode as hex.
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub storage: BTreeMap<H256, H256>,
+}
+
+fn default_init_gas() -> u64 {
+    DEFAULT_INIT_GAS
+}
+
+fn deserialize_balance<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Balance {
+        Number(u64),
+        String(String),
+    }
+
+    match Balance::deserialize(deserializer)? {
+        Balance::Number(n) => Ok(n.into()),
+        Balance::String(s) => s.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+impl GenesisEvmAccount {
+    fn to_evm_account(&self) -> Result<EvmAccount> {
+        Ok(EvmAccount {
+            address: self.address.to_fixed_bytes(),
+            balance: self.balance.into(),
+            nonce: self.nonce.into(),
+            code: hex::decode(self.code.trim_start_matches("0x"))
+                .with_context(|| format!("Invalid code of EVM account {:?}", self.address))?,
+            storage: self
+                .storage
+                .iter()
+                .map(|(index, value)| (index.to_fixed_bytes(), value.to_fixed_bytes()))
+                .collect(),
+        })
+    }
+}
+
+impl Genesis {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("Can't read genesis {:?}", path))?;
+        toml::from_str(&file).with_context(|| format!("Can't parse genesis {:?}", path))
+    }
+
+    /// Writes the genesis into an empty state and returns the initial state root.
+    ///
+    /// Contract paths are resolved relative to `base_dir`.
+    pub fn build(
+        &self,
+        base_dir: &Path,
+        config: &Arc<RuntimeConfig>,
+        state: Arc<RwLock<State>>,
+    ) -> Result<Hash> {
+        if state.read().unwrap().root() != EMPTY_HASH {
+            bail!("State is already initialized");
+        }
+
+        {
+            let mut state = state.write().unwrap();
+            state.set(StateKey::ChainConfig, self.chain.try_to_vec()?);
+
+            for account in self.accounts.iter() {
+                state.create_account(
+                    account.id.clone(),
+                    &Account {
+                        balance: account.balance,
+                        storage_usage: 0,
+                    },
+                    account.evm_address,
+                )?;
+
+                for public_key in account.public_keys.iter() {
+                    state.set_access_key(
+                        account.id.clone(),
+                        public_key.clone(),
+                        &AccessKey::full_access(),
+                    );
+                }
+            }
+
+            for contract in self.contracts.iter() {
+                let path = base_dir.join(&contract.code);
+                let code = std::fs::read(&path)
+                    .with_context(|| format!("Can't read contract {:?}", path))?;
+                if state.get_account(&contract.account)?.is_none() {
+                    state.create_account(
+                        contract.account.clone(),
+                        &Account {
+                            balance: contract.balance,
+                            storage_usage: 0,
+                        },
+                        None,
+                    )?;
+                }
+                state.deploy_contract(contract.account.clone(), code);
+            }
+        }
+
+        for contract in self.contracts.iter() {
+            for call in contract.init.iter() {
+                let signer = call
+                    .signer
+                    .clone()
+                    .unwrap_or_else(|| contract.account.clone());
+
+                execute(
+                    config,
+                    &state,
+                    signer,
+                    contract.account.clone(),
+                    FunctionCall {
+                        method: call.method.clone(),
+                        args: call.args.clone(),
+                    },
+                    call.attached_gas,
+                )
+                .with_context(|| {
+                    format!("Init call {} of {:?} failed", call.method, contract.account)
+                })?;
+            }
+        }
+
+        if !self.evm_accounts.is_empty() {
+            let accounts = self
+                .evm_accounts
+                .iter()
+                .map(GenesisEvmAccount::to_evm_account)
+                .collect::<Result<Vec<_>>>()?;
+
+            let evm = AccountId::new(EVM_CONTRACT.to_string());
+            execute(
+                config,
+                &state,
+                evm.clone(),
+                evm,
+                FunctionCall::new(String::from("init"), accounts),
+                DEFAULT_INIT_GAS,
+            )
+            .context("EVM accounts initialization failed")?;
+        }
+
+        let root = state.write().unwrap().commit()?;
+        info!(
+            chain_id = self.chain.chain_id,
+            root = hex::encode(root),
+            "Genesis state built"
+        );
+
+        Ok(root)
+    }
+}
+
+fn execute(
+    config: &Arc<RuntimeConfig>,
+    state: &Arc<RwLock<State>>,
+    signer: AccountId,
+    contract: AccountId,
+    call: FunctionCall,
+    attached_gas: u64,
+) -> Result<()> {
+    let ctx = Arc::new(RwLock::new(ExecutionContext::new(
+        config.clone(),
+        state.clone(),
+        signer.clone(),
+        signer,
+        contract,
+        attached_gas,
+        call,
+    )));
+
+    executor::execute(ctx.clone())?;
+
+    // Asynchronous and scheduled calls of init calls run once the node is started.
+    let ctx = ctx.read().unwrap();
+    for receipt in ctx.receipts() {
+        state.write().unwrap().push_receipt(&receipt)?;
+    }
+    for scheduled in ctx.scheduled_calls() {
+        state.write().unwrap().push_scheduled_call(&scheduled)?;
+    }
+
+    Ok(())
+}
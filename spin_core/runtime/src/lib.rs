@@ -1,3 +1,9 @@
+pub mod config;
 pub mod context;
 pub mod executor;
+pub mod genesis;
+pub mod profiler;
+pub mod prover;
+pub mod state;
 pub mod syscalls;
+pub mod transaction;
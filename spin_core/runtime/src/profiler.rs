@@ -0,0 +1,167 @@
+use anyhow::Result;
+use elf::{abi::STT_FUNC, endian::LittleEndian, ElfBytes};
+use risc0_zkvm::TraceEvent;
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Environment variable with the directory to write a profile of every execution to.
+pub const PROFILE_DIR_ENV: &str = "SPIN_PROFILE_DIR";
+
+/// Executions profiled by this process, numbering the profile files.
+static PROFILES: AtomicU64 = AtomicU64::new(0);
+
+struct Function {
+    start: u32,
+    end: u32,
+    name: String,
+}
+
+/// Function symbols of a contract ELF, sorted by address.
+pub struct Symbols {
+    functions: Vec<Function>,
+}
+
+impl Symbols {
+    pub fn load(elf: &[u8]) -> Result<Self> {
+        let file = ElfBytes::<LittleEndian>::minimal_parse(elf)?;
+
+        let mut functions = Vec::new();
+        if let Some((symbols, strings)) = file.symbol_table()? {
+            for symbol in symbols.iter() {
+                if symbol.st_symtype() != STT_FUNC || symbol.st_size == 0 {
+                    continue;
+                }
+                let name = strings.get(symbol.st_name as usize)?;
+                functions.push(Function {
+                    start: symbol.st_value as u32,
+                    end: (symbol.st_value + symbol.st_size) as u32,
+                    name: format!("{:#}", rustc_demangle::demangle(name)),
+                });
+            }
+        }
+        functions.sort_by_key(|function| function.start);
+        functions.dedup_by_key(|function| function.start);
+
+        Ok(Self { functions })
+    }
+
+    /// Index of the function containing the address.
+    fn function(&self, pc: u32) -> Option<usize> {
+        let index = self
+            .functions
+            .partition_point(|function| function.start <= pc)
+            .checked_sub(1)?;
+        (pc < self.functions[index].end).then_some(index)
+    }
+
+    fn name(&self, function: Option<usize>) -> &str {
+        function.map_or("[unknown]", |index| &self.functions[index].name)
+    }
+}
+
+/// Attributes the cycles of an execution to the call stacks of the guest.
+///
+/// The stack is rebuilt from the program counter of every instruction: jumping to the start of a
+/// function calls it, and jumping back into a function on the stack returns to it.
+pub struct Profiler {
+    symbols: Symbols,
+    stack: Vec<Option<usize>>,
+    /// Cycle of the last instruction.
+    cycle: u32,
+    /// Cycles spent in the current stack since it was entered.
+    pending: u64,
+    /// Cycles by call stack, outermost function first.
+    cycles: HashMap<Vec<Option<usize>>, u64>,
+}
+
+impl Profiler {
+    pub fn new(symbols: Symbols) -> Self {
+        Self {
+            symbols,
+            stack: Vec::new(),
+            cycle: 0,
+            pending: 0,
+            cycles: HashMap::new(),
+        }
+    }
+
+    /// Directory to write profiles to, if profiling is enabled.
+    pub fn profile_dir() -> Option<PathBuf> {
+        std::env::var_os(PROFILE_DIR_ENV).map(PathBuf::from)
+    }
+
+    pub fn trace(&mut self, event: TraceEvent) -> Result<()> {
+        if let TraceEvent::InstructionStart { cycle, pc, .. } = event {
+            // The cycles since the last instruction were spent by it, in the current stack.
+            self.pending += cycle.saturating_sub(self.cycle) as u64;
+            self.cycle = cycle;
+            self.enter(pc);
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, pc: u32) {
+        let function = self.symbols.function(pc);
+        if self.stack.last() == Some(&function) {
+            return;
+        }
+        self.flush();
+
+        let is_call = function.map_or(false, |index| self.symbols.functions[index].start == pc);
+        if is_call {
+            self.stack.push(function);
+        } else if let Some(depth) = self.stack.iter().rposition(|frame| *frame == function) {
+            self.stack.truncate(depth + 1);
+        } else {
+            // Jump into the middle of another function, e.g. a tail call.
+            self.stack.pop();
+            self.stack.push(function);
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pending > 0 && !self.stack.is_empty() {
+            *self.cycles.entry(self.stack.clone()).or_default() += self.pending;
+        }
+        self.pending = 0;
+    }
+
+    /// Writes the profile in the folded stacks format of flamegraph tools, one
+    /// `outer;inner cycles` line per call stack.
+    pub fn write(&mut self, path: &Path) -> Result<()> {
+        self.flush();
+        let mut stacks = self
+            .cycles
+            .iter()
+            .map(|(stack, cycles)| {
+                let names = stack
+                    .iter()
+                    .map(|function| self.symbols.name(*function))
+                    .collect::<Vec<_>>();
+                (names.join(";"), *cycles)
+            })
+            .collect::<Vec<_>>();
+        stacks.sort();
+
+        let mut folded = String::new();
+        for (stack, cycles) in stacks {
+            writeln!(folded, "{} {}", stack, cycles)?;
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, folded)?;
+        Ok(())
+    }
+
+    /// Path of the next profile in the directory, named after the call.
+    pub fn next_path(dir: &Path, contract: &str, method: &str) -> PathBuf {
+        let profile = PROFILES.fetch_add(1, Ordering::Relaxed);
+        dir.join(format!("{:04}-{}-{}.folded", profile, contract, method))
+    }
+}
@@ -0,0 +1,33 @@
+use anyhow::Result;
+use risc0_zkvm::{sha::Digest, Session};
+use spin_verifier::CallReceipt;
+
+/// Proves executed sessions, in process or with a proving service.
+pub trait Prover: Send + Sync {
+    /// Proves the session of a call running the image.
+    fn prove(&self, image_id: Digest, session: &Session) -> Result<CallReceipt>;
+}
+
+/// Proves sessions in the current process, with the default zkVM prover.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalProver;
+
+impl Prover for LocalProver {
+    fn prove(&self, _image_id: Digest, session: &Session) -> Result<CallReceipt> {
+        Ok(CallReceipt::Session(session.prove()?))
+    }
+}
+
+/// Prover of dev mode, which skips proving and makes fake receipts carrying the real journal.
+///
+/// Fake receipts prove nothing, verifiers only accept them when dev mode is enabled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FakeProver;
+
+impl Prover for FakeProver {
+    fn prove(&self, _image_id: Digest, session: &Session) -> Result<CallReceipt> {
+        Ok(CallReceipt::Fake {
+            journal: session.journal.clone(),
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use anyhow::{bail, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use eth_primitive_types::H160;
+use spin_primitives::AccountId;
+
+use super::{State, StateKey};
+
+/// Contract whose storage holds the account alias registry.
+pub const ALIAS_REGISTRY: &str = super::EVM_CONTRACT;
+
+fn registry() -> AccountId {
+    AccountId::new(ALIAS_REGISTRY.to_string())
+}
+
+fn evm_address_key(account: &AccountId) -> String {
+    format!("evm_address_{}", account)
+}
+
+fn account_id_key(address: &H160) -> String {
+    format!("account_id_{:?}", address)
+}
+
+impl State {
+    /// Returns the EVM address registered for the account, or the one derived from its id.
+    pub fn evm_address(&self, account: &AccountId) -> Result<H160> {
+        match self.get_storage(&registry(), &evm_address_key(account))? {
+            Some(bytes) => Ok(H160::from(<[u8; 20]>::try_from_slice(&bytes)?)),
+            None => Ok(account.derive_evm_address()),
+        }
+    }
+
+    /// Returns the account which registered the EVM address.
+    pub fn account_by_evm_address(&self, address: &H160) -> Result<Option<AccountId>> {
+        self.get_storage(&registry(), &account_id_key(address))?
+            .map(|bytes| Ok(AccountId::try_from_slice(&bytes)?))
+            .transpose()
+    }
+
+    /// Registers the alias in both directions, charging its storage to `payer` if set.
+    pub fn register_evm_address(
+        &mut self,
+        account: &AccountId,
+        address: H160,
+        payer: Option<&AccountId>,
+    ) -> Result<()> {
+        if let Some(owner) = self.account_by_evm_address(&address)? {
+            if &owner != account {
+                bail!(
+                    "EVM address {:?} is already registered by {:?}",
+                    address,
+                    owner
+                );
+            }
+        }
+
+        if self
+            .get_storage(&registry(), &evm_address_key(account))?
+            .is_some()
+        {
+            bail!("Account {:?} already has an EVM address", account);
+        }
+
+        self.set_registry_entry(
+            evm_address_key(account),
+            address.to_fixed_bytes().try_to_vec()?,
+            payer,
+        )?;
+        self.set_registry_entry(account_id_key(&address), account.try_to_vec()?, payer)?;
+
+        Ok(())
+    }
+
+    fn set_registry_entry(
+        &mut self,
+        key: String,
+        value: Vec<u8>,
+        payer: Option<&AccountId>,
+    ) -> Result<()> {
+        match payer {
+            Some(payer) => self.set_storage(registry(), key, value, payer),
+            None => {
+                self.set(StateKey::Storage(registry(), key), value);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use spin_primitives::proof::{internal_hash, leaf_hash, ProofLeaf, StateProof};
+
+pub use spin_primitives::proof::{path_bit, sha256, Hash, EMPTY_HASH};
+
+/// Node of the sparse Merkle tree.
+///
+/// Leaves are placed at the shortest path prefix that is unique to their key hash,
+/// so a subtree with a single leaf is represented by the leaf itself and a subtree
+/// without leaves by [`EMPTY_HASH`]. This keeps the root independent of the
+/// order in which keys were inserted.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub enum Node {
+    Internal { left: Hash, right: Hash },
+    Leaf { key_hash: Hash, value_hash: Hash },
+}
+
+impl Node {
+    pub fn hash(&self) -> Hash {
+        match self {
+            Node::Internal { left, right } => internal_hash(left, right),
+            Node::Leaf {
+                key_hash,
+                value_hash,
+            } => leaf_hash(key_hash, value_hash),
+        }
+    }
+}
+
+/// Sparse Merkle tree over 256-bit key hashes.
+///
+/// Nodes are content-addressed, so every root written to the tree stays readable
+/// until its nodes are removed from the underlying store.
+pub struct MerkleTree<'a> {
+    nodes: &'a sled::Tree,
+}
+
+impl<'a> MerkleTree<'a> {
+    pub fn new(nodes: &'a sled::Tree) -> Self {
+        Self { nodes }
+    }
+
+    pub fn node(&self, hash: &Hash) -> Result<Node> {
+        let bytes = self
+            .nodes
+            .get(hash)?
+            .ok_or_else(|| anyhow!("Merkle node {} not found", hex::encode(hash)))?;
+        Ok(Node::try_from_slice(&bytes)?)
+    }
+
+    fn put(&self, node: Node) -> Result<Hash> {
+        let hash = node.hash();
+        self.nodes.insert(hash, node.try_to_vec()?)?;
+        Ok(hash)
+    }
+
+    fn is_leaf(&self, hash: &Hash) -> Result<bool> {
+        Ok(matches!(self.node(hash)?, Node::Leaf { .. }))
+    }
+
+    /// Returns the value hash stored under the key hash.
+    pub fn get(&self, root: Hash, key_hash: &Hash) -> Result<Option<Hash>> {
+        let mut node_hash = root;
+        let mut depth = 0;
+
+        loop {
+            if node_hash == EMPTY_HASH {
+                return Ok(None);
+            }
+
+            match self.node(&node_hash)? {
+                Node::Leaf {
+                    key_hash: leaf_key_hash,
+                    value_hash,
+                } => return Ok((&leaf_key_hash == key_hash).then_some(value_hash)),
+                Node::Internal { left, right } => {
+                    node_hash = if path_bit(key_hash, depth) {
+                        right
+                    } else {
+                        left
+                    };
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns the key and value hashes of all leaves under the root.
+    pub fn leaves(&self, root: Hash) -> Result<Vec<(Hash, Hash)>> {
+        let mut leaves = Vec::new();
+        let mut pending = vec![root];
+
+        while let Some(node_hash) = pending.pop() {
+            if node_hash == EMPTY_HASH {
+                continue;
+            }
+
+            match self.node(&node_hash)? {
+                Node::Leaf {
+                    key_hash,
+                    value_hash,
+                } => leaves.push((key_hash, value_hash)),
+                Node::Internal { left, right } => {
+                    pending.push(right);
+                    pending.push(left);
+                }
+            }
+        }
+
+        Ok(leaves)
+    }
+
+    /// Returns the proof of the value stored under the key hash, or of its absence.
+    pub fn prove(&self, root: Hash, key_hash: &Hash) -> Result<StateProof> {
+        let mut node_hash = root;
+        let mut siblings = Vec::new();
+
+        loop {
+            if node_hash == EMPTY_HASH {
+                return Ok(StateProof {
+                    siblings,
+                    leaf: None,
+                });
+            }
+
+            match self.node(&node_hash)? {
+                Node::Leaf {
+                    key_hash,
+                    value_hash,
+                } => {
+                    return Ok(StateProof {
+                        siblings,
+                        leaf: Some(ProofLeaf {
+                            key_hash,
+                            value_hash,
+                        }),
+                    })
+                }
+                Node::Internal { left, right } => {
+                    let (next, sibling) = if path_bit(key_hash, siblings.len()) {
+                        (right, left)
+                    } else {
+                        (left, right)
+                    };
+                    siblings.push(sibling);
+                    node_hash = next;
+                }
+            }
+        }
+    }
+
+    /// Inserts or updates the key and returns the new root.
+    pub fn insert(&self, root: Hash, key_hash: &Hash, value_hash: &Hash) -> Result<Hash> {
+        self.insert_at(root, key_hash, value_hash, 0)
+    }
+
+    fn insert_at(
+        &self,
+        node_hash: Hash,
+        key_hash: &Hash,
+        value_hash: &Hash,
+        depth: usize,
+    ) -> Result<Hash> {
+        let leaf = Node::Leaf {
+            key_hash: *key_hash,
+            value_hash: *value_hash,
+        };
+
+        if node_hash == EMPTY_HASH {
+            return self.put(leaf);
+        }
+
+        match self.node(&node_hash)? {
+            Node::Leaf {
+                key_hash: existing_key_hash,
+                ..
+            } => {
+                if &existing_key_hash == key_hash {
+                    return self.put(leaf);
+                }
+
+                let leaf_hash = self.put(leaf)?;
+                self.split(node_hash, &existing_key_hash, leaf_hash, key_hash, depth)
+            }
+            Node::Internal { left, right } => {
+                let (left, right) = if path_bit(key_hash, depth) {
+                    (
+                        left,
+                        self.insert_at(right, key_hash, value_hash, depth + 1)?,
+                    )
+                } else {
+                    (
+                        self.insert_at(left, key_hash, value_hash, depth + 1)?,
+                        right,
+                    )
+                };
+                self.put(Node::Internal { left, right })
+            }
+        }
+    }
+
+    /// Builds the subtree holding two leaves whose key hashes share the first `depth` bits.
+    fn split(
+        &self,
+        first: Hash,
+        first_key_hash: &Hash,
+        second: Hash,
+        second_key_hash: &Hash,
+        depth: usize,
+    ) -> Result<Hash> {
+        let (left, right) = match (
+            path_bit(first_key_hash, depth),
+            path_bit(second_key_hash, depth),
+        ) {
+            (false, true) => (first, second),
+            (true, false) => (second, first),
+            (false, false) => (
+                self.split(first, first_key_hash, second, second_key_hash, depth + 1)?,
+                EMPTY_HASH,
+            ),
+            (true, true) => (
+                EMPTY_HASH,
+                self.split(first, first_key_hash, second, second_key_hash, depth + 1)?,
+            ),
+        };
+
+        self.put(Node::Internal { left, right })
+    }
+
+    /// Removes the key and returns the new root.
+    pub fn remove(&self, root: Hash, key_hash: &Hash) -> Result<Hash> {
+        self.remove_at(root, key_hash, 0)
+    }
+
+    fn remove_at(&self, node_hash: Hash, key_hash: &Hash, depth: usize) -> Result<Hash> {
+        if node_hash == EMPTY_HASH {
+            return Ok(EMPTY_HASH);
+        }
+
+        match self.node(&node_hash)? {
+            Node::Leaf {
+                key_hash: leaf_key_hash,
+                ..
+            } => Ok(if &leaf_key_hash == key_hash {
+                EMPTY_HASH
+            } else {
+                node_hash
+            }),
+            Node::Internal { left, right } => {
+                let (left, right) = if path_bit(key_hash, depth) {
+                    (left, self.remove_at(right, key_hash, depth + 1)?)
+                } else {
+                    (self.remove_at(left, key_hash, depth + 1)?, right)
+                };
+
+                // A subtree left with a single leaf collapses into that leaf.
+                match (left == EMPTY_HASH, right == EMPTY_HASH) {
+                    (true, true) => Ok(EMPTY_HASH),
+                    (true, false) if self.is_leaf(&right)? => Ok(right),
+                    (false, true) if self.is_leaf(&left)? => Ok(left),
+                    _ => self.put(Node::Internal { left, right }),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes() -> sled::Tree {
+        sled::Config::new()
+            .temporary(true)
+            .open()
+            .unwrap()
+            .open_tree("nodes")
+            .unwrap()
+    }
+
+    fn key(i: usize) -> Vec<u8> {
+        format!("key{}", i).into_bytes()
+    }
+
+    fn value(i: usize) -> Vec<u8> {
+        format!("value{}", i).into_bytes()
+    }
+
+    fn insert_all(tree: &MerkleTree, root: Hash, keys: impl Iterator<Item = usize>) -> Hash {
+        keys.fold(root, |root, i| {
+            tree.insert(root, &sha256(&key(i)), &sha256(&value(i)))
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn root_is_independent_of_insertion_order() {
+        let nodes = nodes();
+        let tree = MerkleTree::new(&nodes);
+
+        let ascending = insert_all(&tree, EMPTY_HASH, 0..32);
+        let descending = insert_all(&tree, EMPTY_HASH, (0..32).rev());
+        let interleaved = insert_all(&tree, EMPTY_HASH, (0..32).map(|i| i * 7 % 32));
+
+        assert_ne!(ascending, EMPTY_HASH);
+        assert_eq!(ascending, descending);
+        assert_eq!(ascending, interleaved);
+    }
+
+    #[test]
+    fn updating_a_key_replaces_its_value() {
+        let nodes = nodes();
+        let tree = MerkleTree::new(&nodes);
+        let root = insert_all(&tree, EMPTY_HASH, 0..8);
+
+        let updated = tree
+            .insert(root, &sha256(&key(3)), &sha256(b"other value"))
+            .unwrap();
+
+        assert_ne!(updated, root);
+        assert_eq!(
+            tree.get(updated, &sha256(&key(3))).unwrap(),
+            Some(sha256(b"other value"))
+        );
+        assert_eq!(tree.leaves(updated).unwrap().len(), 8);
+    }
+
+    #[test]
+    fn remove_restores_previous_root() {
+        let nodes = nodes();
+        let tree = MerkleTree::new(&nodes);
+
+        let root = insert_all(&tree, EMPTY_HASH, 0..16);
+        let with_key = insert_all(&tree, root, 16..17);
+        assert_ne!(with_key, root);
+        assert_eq!(tree.remove(with_key, &sha256(&key(16))).unwrap(), root);
+
+        // Removing every key but one collapses the tree back into that leaf.
+        let single = insert_all(&tree, EMPTY_HASH, 0..1);
+        let collapsed = (1..16).fold(root, |root, i| tree.remove(root, &sha256(&key(i))).unwrap());
+        assert_eq!(collapsed, single);
+        assert_eq!(
+            tree.remove(collapsed, &sha256(&key(0))).unwrap(),
+            EMPTY_HASH
+        );
+    }
+
+    #[test]
+    fn removing_absent_key_keeps_root() {
+        let nodes = nodes();
+        let tree = MerkleTree::new(&nodes);
+        let root = insert_all(&tree, EMPTY_HASH, 0..8);
+
+        assert_eq!(tree.remove(root, &sha256(&key(8))).unwrap(), root);
+        assert_eq!(
+            tree.remove(EMPTY_HASH, &sha256(&key(0))).unwrap(),
+            EMPTY_HASH
+        );
+    }
+
+    #[test]
+    fn proofs_verify_inclusion_and_non_inclusion() {
+        let nodes = nodes();
+        let tree = MerkleTree::new(&nodes);
+        let root = insert_all(&tree, EMPTY_HASH, 0..16);
+
+        for i in 0..16 {
+            let proof = tree.prove(root, &sha256(&key(i))).unwrap();
+            assert_eq!(proof.verify(&root, &key(i), Some(&value(i))), Ok(()));
+            assert!(proof.verify(&root, &key(i), None).is_err());
+        }
+        for i in 16..48 {
+            let proof = tree.prove(root, &sha256(&key(i))).unwrap();
+            assert_eq!(proof.verify(&root, &key(i), None), Ok(()));
+            assert!(proof.verify(&root, &key(i), Some(&value(i))).is_err());
+        }
+
+        let proof = tree.prove(EMPTY_HASH, &sha256(&key(0))).unwrap();
+        assert_eq!(proof.verify(&EMPTY_HASH, &key(0), None), Ok(()));
+    }
+}
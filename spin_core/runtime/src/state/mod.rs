@@ -0,0 +1,556 @@
+use anyhow::{anyhow, bail, Result};
+use borsh::BorshDeserialize;
+use eth_primitive_types::H160;
+use spin_primitives::{
+    proof::{storage_state_key, StateProof},
+    AccessKey, Account, AccountId, PublicKey,
+};
+use tracing::debug;
+
+use crate::genesis::ChainConfig;
+
+use std::{collections::BTreeMap, path::Path};
+
+pub mod aliases;
+pub mod merkle;
+pub mod pruning;
+pub mod receipts;
+pub mod schedule;
+pub mod snapshot;
+
+use merkle::{sha256, Hash, MerkleTree, EMPTY_HASH};
+
+const ROOT_KEY: &[u8] = b"root";
+
+/// Bytes charged for every storage entry on top of its key and value.
+pub const STORAGE_ENTRY_OVERHEAD: u64 = 40;
+
+/// Built-in EVM meta contract, which holds the storage of all EVM accounts and the alias registry.
+pub const EVM_CONTRACT: &str = "evm";
+
+fn storage_entry_size(key: &str, value: &[u8]) -> u64 {
+    STORAGE_ENTRY_OVERHEAD + (key.len() + value.len()) as u64
+}
+
+/// Key of a value in the state.
+///
+/// All values are committed to by a single sparse Merkle tree; the key is hashed
+/// to find the leaf path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateKey {
+    /// Chain parameters the state was created with.
+    ChainConfig,
+    Account(AccountId),
+    AccessKey(AccountId, PublicKey),
+    Code(AccountId),
+    Storage(AccountId, String),
+    /// Id of the next scheduled receipt.
+    NextReceiptId,
+    Receipt(u64),
+    /// Latest produced block.
+    Block,
+    NextScheduledCallId,
+    ScheduledCall(u64),
+}
+
+impl StateKey {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            StateKey::ChainConfig => b"chain_config".to_vec(),
+            StateKey::Account(account) => format!("a/{}", account).into_bytes(),
+            StateKey::AccessKey(account, public_key) => {
+                format!("k/{}/{}", account, hex::encode(public_key.0)).into_bytes()
+            }
+            StateKey::Code(account) => format!("c/{}", account).into_bytes(),
+            StateKey::Storage(account, key) => storage_state_key(account, key),
+            StateKey::NextReceiptId => b"next_receipt_id".to_vec(),
+            // Zero-padded, so that receipts are sorted by id.
+            StateKey::Receipt(id) => format!("r/{:020}", id).into_bytes(),
+            StateKey::Block => b"block".to_vec(),
+            StateKey::NextScheduledCallId => b"next_scheduled_call_id".to_vec(),
+            StateKey::ScheduledCall(id) => format!("sc/{:020}", id).into_bytes(),
+        }
+    }
+
+    fn storage_prefix(account: &AccountId) -> Vec<u8> {
+        format!("s/{}/", account).into_bytes()
+    }
+}
+
+/// Accounts, contract code and storage of all contracts.
+///
+/// Changes made by executions are kept in memory until [`State::commit`] is called,
+/// so failed executions and view calls can be dropped with [`State::rollback`].
+pub struct State {
+    db: sled::Db,
+    /// Merkle nodes by their hash.
+    nodes: sled::Tree,
+    /// Values by their hash.
+    values: sled::Tree,
+    /// State keys by their hash.
+    keys: sled::Tree,
+    /// Latest committed values by state key.
+    latest: sled::Tree,
+    /// Root of the last commit of every block, by big-endian block height.
+    block_roots: sled::Tree,
+    root: Hash,
+    /// Set for a read-only view of a past root, which reads through the Merkle tree.
+    historical: bool,
+    changes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// Pending changes at the start of every nested call which may still be reverted.
+    checkpoints: Vec<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+}
+
+impl State {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_db(sled::open(path.as_ref())?)
+    }
+
+    /// Opens a state in a temporary database, deleted when the state is dropped.
+    pub fn open_temporary() -> Result<Self> {
+        Self::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    fn from_db(db: sled::Db) -> Result<Self> {
+        let root = match db.get(ROOT_KEY)? {
+            Some(root) => root
+                .as_ref()
+                .try_into()
+                .map_err(|_| anyhow!("Invalid state root"))?,
+            None => EMPTY_HASH,
+        };
+
+        Ok(Self {
+            nodes: db.open_tree("nodes")?,
+            values: db.open_tree("values")?,
+            keys: db.open_tree("keys")?,
+            latest: db.open_tree("latest")?,
+            block_roots: db.open_tree("block_roots")?,
+            db,
+            root,
+            historical: false,
+            changes: BTreeMap::new(),
+            checkpoints: Vec::new(),
+        })
+    }
+
+    /// Opens a tree of the state database for data kept outside of the state, like node indexes.
+    pub fn open_tree(&self, name: &str) -> Result<sled::Tree> {
+        Ok(self.db.open_tree(name)?)
+    }
+
+    /// Root of the last committed state.
+    pub fn root(&self) -> Hash {
+        self.root
+    }
+
+    /// State root at the end of the block, `None` if the state has no root of that block.
+    pub fn root_at(&self, height: u64) -> Result<Option<Hash>> {
+        self.block_roots
+            .get(height.to_be_bytes())?
+            .map(|root| {
+                root.as_ref()
+                    .try_into()
+                    .map_err(|_| anyhow!("Invalid state root of block {}", height))
+            })
+            .transpose()
+    }
+
+    /// Read-only view of the state at a committed root.
+    ///
+    /// The view can execute calls, but its changes can't be committed.
+    pub fn at_root(&self, root: Hash) -> State {
+        State {
+            db: self.db.clone(),
+            nodes: self.nodes.clone(),
+            values: self.values.clone(),
+            keys: self.keys.clone(),
+            latest: self.latest.clone(),
+            block_roots: self.block_roots.clone(),
+            root,
+            historical: true,
+            changes: BTreeMap::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Read-only view of the state at the end of the block.
+    ///
+    /// Fails if the block isn't produced yet or its state was pruned.
+    pub fn at_height(&self, height: u64) -> Result<State> {
+        let root = self
+            .root_at(height)?
+            .ok_or_else(|| anyhow!("State of block {} is not available", height))?;
+        Ok(self.at_root(root))
+    }
+
+    pub fn get(&self, key: &StateKey) -> Result<Option<Vec<u8>>> {
+        let key = key.to_bytes();
+        if let Some(change) = self.changes.get(&key) {
+            return Ok(change.clone());
+        }
+
+        if self.historical {
+            return MerkleTree::new(&self.nodes)
+                .get(self.root, &sha256(&key))?
+                .map(|value_hash| self.value(&value_hash))
+                .transpose();
+        }
+        Ok(self.latest.get(&key)?.map(|value| value.to_vec()))
+    }
+
+    fn value(&self, value_hash: &Hash) -> Result<Vec<u8>> {
+        Ok(self
+            .values
+            .get(value_hash)?
+            .ok_or_else(|| anyhow!("Value {} not found", hex::encode(value_hash)))?
+            .to_vec())
+    }
+
+    pub fn set(&mut self, key: StateKey, value: Vec<u8>) {
+        self.changes.insert(key.to_bytes(), Some(value));
+    }
+
+    pub fn remove(&mut self, key: StateKey) {
+        self.changes.insert(key.to_bytes(), None);
+    }
+
+    /// Chain parameters written by the genesis, `None` for a state built without one.
+    pub fn chain_config(&self) -> Result<Option<ChainConfig>> {
+        self.get(&StateKey::ChainConfig)?
+            .map(|bytes| Ok(ChainConfig::try_from_slice(&bytes)?))
+            .transpose()
+    }
+
+    pub fn get_account(&self, account: &AccountId) -> Result<Option<Account>> {
+        self.get(&StateKey::Account(account.clone()))?
+            .map(|bytes| Ok(Account::try_from_bytes(bytes)?))
+            .transpose()
+    }
+
+    pub fn set_account(&mut self, account_id: AccountId, account: &Account) {
+        self.set(StateKey::Account(account_id), account.into_bytes());
+    }
+
+    pub fn get_access_key(
+        &self,
+        account: &AccountId,
+        public_key: &PublicKey,
+    ) -> Result<Option<AccessKey>> {
+        self.get(&StateKey::AccessKey(account.clone(), public_key.clone()))?
+            .map(|bytes| Ok(AccessKey::try_from_bytes(bytes)?))
+            .transpose()
+    }
+
+    pub fn set_access_key(&mut self, account: AccountId, public_key: PublicKey, key: &AccessKey) {
+        self.set(StateKey::AccessKey(account, public_key), key.into_bytes());
+    }
+
+    pub fn remove_access_key(&mut self, account: AccountId, public_key: PublicKey) {
+        self.remove(StateKey::AccessKey(account, public_key));
+    }
+
+    /// Creates the account and registers its EVM alias, derived from the id if not given.
+    ///
+    /// Used by the genesis, the storage of the alias isn't charged to any account.
+    pub fn create_account(
+        &mut self,
+        account_id: AccountId,
+        account: &Account,
+        evm_address: Option<H160>,
+    ) -> Result<()> {
+        self.insert_account(account_id, account, evm_address, None)
+    }
+
+    fn insert_account(
+        &mut self,
+        account_id: AccountId,
+        account: &Account,
+        evm_address: Option<H160>,
+        payer: Option<&AccountId>,
+    ) -> Result<()> {
+        if account_id.is_evm() {
+            bail!("Account {} is owned by an EVM address", account_id);
+        }
+        if self.get_account(&account_id)?.is_some() {
+            bail!("Account {} already exists", account_id);
+        }
+
+        let evm_address = evm_address.unwrap_or_else(|| account_id.derive_evm_address());
+        self.register_evm_address(&account_id, evm_address, payer)?;
+        self.set_account(account_id, account);
+
+        Ok(())
+    }
+
+    /// Creates a direct sub-account of `creator`, top-level accounts can only be created at genesis.
+    ///
+    /// The storage of the EVM alias of the account is charged to its creator.
+    pub fn create_sub_account(
+        &mut self,
+        creator: &AccountId,
+        account_id: AccountId,
+        account: &Account,
+    ) -> Result<()> {
+        if !account_id.is_sub_account_of(creator) {
+            bail!("Account {} can't be created by {}", account_id, creator);
+        }
+        if self.get_account(creator)?.is_none() {
+            bail!("Account {} doesn't exist", creator);
+        }
+
+        self.insert_account(account_id, account, None, Some(creator))
+    }
+
+    pub fn load_contract(&self, account: &AccountId) -> Result<Vec<u8>> {
+        self.get(&StateKey::Code(account.clone()))?
+            .ok_or_else(|| anyhow!("Contract {} not found", account))
+    }
+
+    pub fn deploy_contract(&mut self, account: AccountId, code: Vec<u8>) {
+        debug!(?account, size = code.len(), "Deploying contract");
+        self.set(StateKey::Code(account), code);
+    }
+
+    pub fn get_storage(&self, contract: &AccountId, key: &str) -> Result<Option<Vec<u8>>> {
+        self.get(&StateKey::Storage(contract.clone(), key.to_string()))
+    }
+
+    /// Writes the value, locking balance of `payer` for the bytes it adds, see
+    /// [`ExecutionContext::storage_payer`](crate::context::ExecutionContext::storage_payer).
+    ///
+    /// Fails if the balance of the payer doesn't cover its new storage usage.
+    pub fn set_storage(
+        &mut self,
+        contract: AccountId,
+        key: String,
+        value: Vec<u8>,
+        payer: &AccountId,
+    ) -> Result<()> {
+        let previous = self.get_storage(&contract, &key)?;
+        let previous_size = previous.map_or(0, |value| storage_entry_size(&key, &value));
+        self.update_storage_usage(payer, previous_size, storage_entry_size(&key, &value))?;
+
+        self.set(StateKey::Storage(contract, key), value);
+        Ok(())
+    }
+
+    /// Removes the value, releasing balance of `payer` locked for it.
+    pub fn remove_storage(
+        &mut self,
+        contract: AccountId,
+        key: String,
+        payer: &AccountId,
+    ) -> Result<()> {
+        if let Some(previous) = self.get_storage(&contract, &key)? {
+            self.update_storage_usage(payer, storage_entry_size(&key, &previous), 0)?;
+            self.remove(StateKey::Storage(contract, key));
+        }
+        Ok(())
+    }
+
+    fn update_storage_usage(
+        &mut self,
+        payer: &AccountId,
+        previous_size: u64,
+        new_size: u64,
+    ) -> Result<()> {
+        // Only the genesis calls the EVM contract as itself, to initialize the EVM accounts.
+        if payer.as_str() == EVM_CONTRACT {
+            return Ok(());
+        }
+
+        let mut account = self
+            .get_account(payer)?
+            .ok_or_else(|| anyhow!("Account {} doesn't exist", payer))?;
+        account.storage_usage = account.storage_usage.saturating_sub(previous_size) + new_size;
+
+        if new_size > previous_size {
+            let storage_byte_cost = self.storage_byte_cost()?;
+            let locked = account.locked_balance(storage_byte_cost);
+            if account.balance < locked {
+                bail!(
+                    "Account {} needs a balance of {} to store {} bytes, but has {}",
+                    payer,
+                    locked,
+                    account.storage_usage,
+                    account.balance
+                );
+            }
+        }
+
+        self.set_account(payer.clone(), &account);
+        Ok(())
+    }
+
+    pub fn storage_byte_cost(&self) -> Result<u128> {
+        Ok(self
+            .chain_config()?
+            .map_or(0, |chain| chain.storage_byte_cost))
+    }
+
+    /// Returns all storage keys and values of the contract, sorted by key.
+    pub fn storage_entries(&self, contract: &AccountId) -> Result<Vec<(String, Vec<u8>)>> {
+        let prefix = StateKey::storage_prefix(contract);
+
+        Ok(self
+            .entries_with_prefix(&prefix)?
+            .into_iter()
+            .map(|(key, value)| {
+                let key = String::from_utf8_lossy(&key[prefix.len()..]).to_string();
+                (key, value)
+            })
+            .collect())
+    }
+
+    /// Returns the storage value of the contract at a committed state root, the latest one if
+    /// `root` is `None`, with the proof of its inclusion or absence.
+    pub fn storage_proof(
+        &self,
+        root: Option<Hash>,
+        contract: &AccountId,
+        key: &str,
+    ) -> Result<(Option<Vec<u8>>, StateProof)> {
+        let root = root.unwrap_or(self.root);
+        let key_hash = sha256(&StateKey::Storage(contract.clone(), key.to_string()).to_bytes());
+        let proof = MerkleTree::new(&self.nodes).prove(root, &key_hash)?;
+
+        let value = match &proof.leaf {
+            Some(leaf) if leaf.key_hash == key_hash => Some(self.value(&leaf.value_hash)?),
+            _ => None,
+        };
+
+        Ok((value, proof))
+    }
+
+    /// Returns up to `limit` storage entries of the contract whose keys start with `prefix`, from
+    /// the `cursor` key on, and the key to start the next page from.
+    pub fn storage_page(
+        &self,
+        contract: &AccountId,
+        prefix: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<(String, Vec<u8>)>, Option<String>)> {
+        let contract_prefix = StateKey::storage_prefix(contract);
+        let mut full_prefix = contract_prefix.clone();
+        full_prefix.extend_from_slice(prefix.as_bytes());
+
+        let mut entries = self
+            .entries_with_prefix(&full_prefix)?
+            .into_iter()
+            .map(|(key, value)| {
+                let key = String::from_utf8_lossy(&key[contract_prefix.len()..]).to_string();
+                (key, value)
+            })
+            .filter(|(key, _)| cursor.map_or(true, |cursor| key.as_str() >= cursor))
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        let next_cursor = if entries.len() > limit {
+            entries.pop().map(|(key, _)| key)
+        } else {
+            None
+        };
+
+        Ok((entries, next_cursor))
+    }
+
+    /// Returns all values whose state keys start with the prefix, including pending changes.
+    ///
+    /// A historical view walks all leaves of its root, which is slow for large states.
+    fn entries_with_prefix(&self, prefix: &[u8]) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+        let mut entries = BTreeMap::new();
+
+        if self.historical {
+            for (key_hash, value_hash) in MerkleTree::new(&self.nodes).leaves(self.root)? {
+                let key = self
+                    .keys
+                    .get(key_hash)?
+                    .ok_or_else(|| anyhow!("State key {} not found", hex::encode(key_hash)))?;
+                if key.starts_with(prefix) {
+                    entries.insert(key.to_vec(), Some(self.value(&value_hash)?));
+                }
+            }
+        } else {
+            for entry in self.latest.scan_prefix(prefix) {
+                let (key, value) = entry?;
+                entries.insert(key.to_vec(), Some(value.to_vec()));
+            }
+        }
+
+        for (key, change) in self.changes.range(prefix.to_vec()..) {
+            if !key.starts_with(prefix) {
+                break;
+            }
+            entries.insert(key.clone(), change.clone());
+        }
+
+        Ok(entries
+            .into_iter()
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .collect())
+    }
+
+    /// Writes all pending changes and returns the new state root.
+    pub fn commit(&mut self) -> Result<Hash> {
+        if self.historical {
+            bail!("Historical state can't be committed");
+        }
+        self.checkpoints.clear();
+        let tree = MerkleTree::new(&self.nodes);
+        let mut root = self.root;
+
+        for (key, change) in std::mem::take(&mut self.changes) {
+            let key_hash = sha256(&key);
+
+            match change {
+                Some(value) => {
+                    let value_hash = sha256(&value);
+                    self.values.insert(value_hash, value.as_slice())?;
+                    self.keys.insert(key_hash, key.as_slice())?;
+                    self.latest.insert(&key, value)?;
+                    root = tree.insert(root, &key_hash, &value_hash)?;
+                }
+                None => {
+                    self.latest.remove(&key)?;
+                    root = tree.remove(root, &key_hash)?;
+                }
+            }
+        }
+
+        self.db.insert(ROOT_KEY, root.to_vec())?;
+        let height = self.block()?.height;
+        self.block_roots
+            .insert(height.to_be_bytes(), root.to_vec())?;
+        self.db.flush()?;
+        self.root = root;
+
+        debug!(root = hex::encode(root), "State committed");
+        Ok(root)
+    }
+
+    /// Drops all pending changes.
+    pub fn rollback(&mut self) {
+        self.changes.clear();
+        self.checkpoints.clear();
+    }
+
+    /// Starts a nested set of changes, which is either kept by [`State::discard_checkpoint`]
+    /// or dropped by [`State::revert_to_checkpoint`].
+    pub fn checkpoint(&mut self) {
+        self.checkpoints.push(self.changes.clone());
+    }
+
+    /// Keeps the changes made since the last checkpoint.
+    pub fn discard_checkpoint(&mut self) {
+        self.checkpoints.pop();
+    }
+
+    /// Drops the changes made since the last checkpoint.
+    pub fn revert_to_checkpoint(&mut self) {
+        if let Some(changes) = self.checkpoints.pop() {
+            self.changes = changes;
+        }
+    }
+}
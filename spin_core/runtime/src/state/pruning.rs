@@ -0,0 +1,273 @@
+use anyhow::{bail, Result};
+use tracing::info;
+
+use std::collections::HashSet;
+
+use super::{
+    merkle::{Hash, MerkleTree, Node, EMPTY_HASH},
+    State,
+};
+
+/// How much state history a node keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PruningMode {
+    /// Keeps the state of every block.
+    #[default]
+    Archive,
+    /// Keeps the state of the last `keep_blocks` blocks and of every `checkpoint_interval`-th
+    /// block, no checkpoints if it is zero.
+    Prune {
+        keep_blocks: u64,
+        checkpoint_interval: u64,
+    },
+}
+
+impl PruningMode {
+    pub fn retains(&self, height: u64, latest_height: u64) -> bool {
+        match *self {
+            PruningMode::Archive => true,
+            PruningMode::Prune {
+                keep_blocks,
+                checkpoint_interval,
+            } => {
+                height + keep_blocks > latest_height
+                    || (checkpoint_interval > 0 && height % checkpoint_interval == 0)
+            }
+        }
+    }
+}
+
+/// What [`State::prune`] deleted.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PruningStats {
+    pub blocks: usize,
+    pub nodes: usize,
+    pub values: usize,
+}
+
+impl State {
+    /// Forgets the roots of blocks the mode doesn't retain and deletes the Merkle nodes, values
+    /// and keys which only they reached.
+    ///
+    /// `retained_roots` are kept too, like the roots within blocks that unproven transactions
+    /// are replayed on. Must be called without pending changes.
+    pub fn prune(&mut self, mode: PruningMode, retained_roots: &[Hash]) -> Result<PruningStats> {
+        if !self.changes.is_empty() {
+            bail!("State can't be pruned with pending changes");
+        }
+
+        let mut stats = PruningStats::default();
+        if mode == PruningMode::Archive {
+            return Ok(stats);
+        }
+
+        let latest_height = self.block()?.height;
+        let mut retained_roots = retained_roots.to_vec();
+        retained_roots.push(self.root);
+        for entry in self.block_roots.iter() {
+            let (height, root) = entry?;
+            let height = u64::from_be_bytes(height.as_ref().try_into()?);
+            if mode.retains(height, latest_height) {
+                retained_roots.push(root.as_ref().try_into()?);
+            } else {
+                self.block_roots.remove(height.to_be_bytes())?;
+                stats.blocks += 1;
+            }
+        }
+
+        let reachable = self.reachable(&retained_roots)?;
+
+        for entry in self.nodes.iter() {
+            let (hash, _) = entry?;
+            if !reachable.nodes.contains(hash.as_ref()) {
+                self.nodes.remove(hash)?;
+                stats.nodes += 1;
+            }
+        }
+        for entry in self.values.iter() {
+            let (hash, _) = entry?;
+            if !reachable.values.contains(hash.as_ref()) {
+                self.values.remove(hash)?;
+                stats.values += 1;
+            }
+        }
+        for entry in self.keys.iter() {
+            let (hash, _) = entry?;
+            if !reachable.keys.contains(hash.as_ref()) {
+                self.keys.remove(hash)?;
+            }
+        }
+        self.db.flush()?;
+
+        info!(
+            latest_height,
+            blocks = stats.blocks,
+            nodes = stats.nodes,
+            values = stats.values,
+            "State pruned"
+        );
+        Ok(stats)
+    }
+
+    fn reachable(&self, roots: &[Hash]) -> Result<Reachable> {
+        let tree = MerkleTree::new(&self.nodes);
+        let mut reachable = Reachable::default();
+        let mut pending = roots.to_vec();
+
+        while let Some(node_hash) = pending.pop() {
+            if node_hash == EMPTY_HASH || !reachable.nodes.insert(node_hash) {
+                continue;
+            }
+
+            match tree.node(&node_hash)? {
+                Node::Leaf {
+                    key_hash,
+                    value_hash,
+                } => {
+                    reachable.keys.insert(key_hash);
+                    reachable.values.insert(value_hash);
+                }
+                Node::Internal { left, right } => {
+                    pending.push(left);
+                    pending.push(right);
+                }
+            }
+        }
+
+        Ok(reachable)
+    }
+}
+
+#[derive(Default)]
+struct Reachable {
+    nodes: HashSet<Hash>,
+    values: HashSet<Hash>,
+    keys: HashSet<Hash>,
+}
+
+#[cfg(test)]
+mod tests {
+    use spin_primitives::{AccountId, BlockInfo};
+
+    use super::*;
+    use crate::state::StateKey;
+
+    fn key(key: &str) -> StateKey {
+        StateKey::Storage(AccountId::new(String::from("token.spin")), key.to_string())
+    }
+
+    fn value(state: &State, name: &str) -> Option<String> {
+        state
+            .get(&key(name))
+            .unwrap()
+            .map(|value| String::from_utf8(value).unwrap())
+    }
+
+    /// Commits the block with the values set, or removed if `None`.
+    fn commit_block(state: &mut State, height: u64, values: &[(&str, Option<&str>)]) {
+        state
+            .set_block(&BlockInfo {
+                height,
+                timestamp: height * 1000,
+            })
+            .unwrap();
+        for (name, value) in values {
+            match value {
+                Some(value) => state.set(key(name), value.as_bytes().to_vec()),
+                None => state.remove(key(name)),
+            }
+        }
+        state.commit().unwrap();
+    }
+
+    fn state() -> State {
+        let mut state = State::open_temporary().unwrap();
+        commit_block(
+            &mut state,
+            1,
+            &[("a", Some("a1")), ("b", Some("b1")), ("c", Some("c1"))],
+        );
+        commit_block(&mut state, 2, &[("b", Some("b2"))]);
+        commit_block(&mut state, 3, &[("b", Some("b3")), ("c", None)]);
+        commit_block(&mut state, 4, &[("b", Some("b4")), ("d", Some("d4"))]);
+        commit_block(&mut state, 5, &[("b", Some("b5"))]);
+        state
+    }
+
+    #[test]
+    fn retained_roots_stay_readable() {
+        let mut state = state();
+        let stats = state
+            .prune(
+                PruningMode::Prune {
+                    keep_blocks: 2,
+                    checkpoint_interval: 0,
+                },
+                &[],
+            )
+            .unwrap();
+
+        assert_eq!(stats.blocks, 3);
+        assert!(stats.nodes > 0);
+        assert!(stats.values > 0);
+
+        let block_4 = state.at_height(4).unwrap();
+        assert_eq!(value(&block_4, "a").as_deref(), Some("a1"));
+        assert_eq!(value(&block_4, "b").as_deref(), Some("b4"));
+        assert_eq!(value(&block_4, "c"), None);
+        assert_eq!(value(&block_4, "d").as_deref(), Some("d4"));
+
+        let block_5 = state.at_root(state.root());
+        assert_eq!(value(&block_5, "a").as_deref(), Some("a1"));
+        assert_eq!(value(&block_5, "b").as_deref(), Some("b5"));
+        assert_eq!(value(&block_5, "d").as_deref(), Some("d4"));
+
+        assert!(state.at_height(3).is_err());
+    }
+
+    #[test]
+    fn extra_retained_roots_stay_readable() {
+        let mut state = state();
+        let root = state.root_at(2).unwrap().unwrap();
+        state
+            .prune(
+                PruningMode::Prune {
+                    keep_blocks: 1,
+                    checkpoint_interval: 0,
+                },
+                &[root],
+            )
+            .unwrap();
+
+        let view = state.at_root(root);
+        assert_eq!(value(&view, "a").as_deref(), Some("a1"));
+        assert_eq!(value(&view, "b").as_deref(), Some("b2"));
+        assert_eq!(value(&view, "c").as_deref(), Some("c1"));
+
+        // The root isn't the root of a retained block.
+        assert!(state.at_height(2).is_err());
+    }
+
+    #[test]
+    fn checkpoints_stay_readable() {
+        let mut state = state();
+        state
+            .prune(
+                PruningMode::Prune {
+                    keep_blocks: 1,
+                    checkpoint_interval: 2,
+                },
+                &[],
+            )
+            .unwrap();
+
+        let block_2 = state.at_height(2).unwrap();
+        assert_eq!(value(&block_2, "a").as_deref(), Some("a1"));
+        assert_eq!(value(&block_2, "b").as_deref(), Some("b2"));
+        assert_eq!(value(&block_2, "c").as_deref(), Some("c1"));
+        assert_eq!(value(&block_2, "d"), None);
+
+        assert!(state.at_height(1).is_err());
+        assert!(state.at_height(3).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
+use spin_primitives::{AccountId, Callback, ContractCall};
+
+use super::{State, StateKey};
+
+const RECEIPT_PREFIX: &[u8] = b"r/";
+
+/// Asynchronous call waiting in the queue to be executed as a separate execution.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct Receipt {
+    pub signer: AccountId,
+    /// Contract which scheduled the call.
+    pub caller: AccountId,
+    pub call: ContractCall,
+    /// Called on `caller` with the result of `call`.
+    pub callback: Option<Callback>,
+}
+
+impl State {
+    /// Appends the receipt to the queue and returns its id.
+    pub fn push_receipt(&mut self, receipt: &Receipt) -> Result<u64> {
+        let id = match self.get(&StateKey::NextReceiptId)? {
+            Some(bytes) => u64::try_from_slice(&bytes)?,
+            None => 0,
+        };
+
+        self.set(StateKey::Receipt(id), receipt.try_to_vec()?);
+        self.set(StateKey::NextReceiptId, (id + 1).try_to_vec()?);
+
+        Ok(id)
+    }
+
+    /// Returns the oldest receipt in the queue without removing it.
+    pub fn next_receipt(&self) -> Result<Option<(u64, Receipt)>> {
+        let (key, value) = match self.entries_with_prefix(RECEIPT_PREFIX)?.into_iter().next() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let id = String::from_utf8_lossy(&key[RECEIPT_PREFIX.len()..]).parse()?;
+        Ok(Some((id, Receipt::try_from_slice(&value)?)))
+    }
+
+    pub fn remove_receipt(&mut self, id: u64) {
+        self.remove(StateKey::Receipt(id));
+    }
+}
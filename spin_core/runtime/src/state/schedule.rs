@@ -0,0 +1,69 @@
+use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
+use spin_primitives::{AccountId, BlockInfo, ContractCall, Schedule};
+
+use super::{State, StateKey};
+
+const SCHEDULED_CALL_PREFIX: &[u8] = b"sc/";
+
+/// Call scheduled by a contract to run at a future block, with its gas prepaid.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct ScheduledCall {
+    pub signer: AccountId,
+    /// Contract which scheduled the call.
+    pub caller: AccountId,
+    pub call: ContractCall,
+    pub schedule: Schedule,
+}
+
+impl State {
+    /// Returns the latest produced block, the default one before the first block.
+    pub fn block(&self) -> Result<BlockInfo> {
+        match self.get(&StateKey::Block)? {
+            Some(bytes) => Ok(BlockInfo::try_from_slice(&bytes)?),
+            None => Ok(BlockInfo::default()),
+        }
+    }
+
+    pub fn set_block(&mut self, block: &BlockInfo) -> Result<()> {
+        self.set(StateKey::Block, block.try_to_vec()?);
+        Ok(())
+    }
+
+    /// Stores the scheduled call and returns its id.
+    pub fn push_scheduled_call(&mut self, scheduled: &ScheduledCall) -> Result<u64> {
+        let id = match self.get(&StateKey::NextScheduledCallId)? {
+            Some(bytes) => u64::try_from_slice(&bytes)?,
+            None => 0,
+        };
+
+        self.set(StateKey::ScheduledCall(id), scheduled.try_to_vec()?);
+        self.set(StateKey::NextScheduledCallId, (id + 1).try_to_vec()?);
+
+        Ok(id)
+    }
+
+    /// Returns the scheduled calls due in the block, in the order they were scheduled.
+    pub fn due_scheduled_calls(&self, block: &BlockInfo) -> Result<Vec<(u64, ScheduledCall)>> {
+        let mut due = Vec::new();
+
+        for (key, value) in self.entries_with_prefix(SCHEDULED_CALL_PREFIX)? {
+            let scheduled = ScheduledCall::try_from_slice(&value)?;
+            if scheduled.schedule.at.is_due(block) {
+                let id = String::from_utf8_lossy(&key[SCHEDULED_CALL_PREFIX.len()..]).parse()?;
+                due.push((id, scheduled));
+            }
+        }
+
+        Ok(due)
+    }
+
+    pub fn set_scheduled_call(&mut self, id: u64, scheduled: &ScheduledCall) -> Result<()> {
+        self.set(StateKey::ScheduledCall(id), scheduled.try_to_vec()?);
+        Ok(())
+    }
+
+    pub fn remove_scheduled_call(&mut self, id: u64) {
+        self.remove(StateKey::ScheduledCall(id));
+    }
+}
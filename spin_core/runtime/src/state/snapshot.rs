@@ -0,0 +1,134 @@
+use anyhow::{anyhow, bail, Context, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use spin_primitives::BlockInfo;
+use tracing::info;
+
+use std::path::Path;
+
+use super::{
+    merkle::{sha256, Hash, MerkleTree, EMPTY_HASH},
+    State, StateKey,
+};
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"SPINSNAP";
+
+/// Version of the snapshot format, snapshots of other versions are rejected.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Full state at a state root: accounts, keys, contract code, storage and queues.
+///
+/// The file holds the magic bytes, the version as little-endian `u32`, the Borsh-encoded
+/// snapshot and the SHA-256 checksum of everything before it.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct Snapshot {
+    pub root: Hash,
+    pub block: BlockInfo,
+    /// State keys and values sorted by key.
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl State {
+    /// Collects all keys and values at a committed state root.
+    pub fn snapshot(&self, root: Hash) -> Result<Snapshot> {
+        let mut entries = MerkleTree::new(&self.nodes)
+            .leaves(root)?
+            .into_iter()
+            .map(|(key_hash, value_hash)| {
+                let key = self
+                    .keys
+                    .get(key_hash)?
+                    .ok_or_else(|| anyhow!("State key {} not found", hex::encode(key_hash)))?;
+                let value = self
+                    .values
+                    .get(value_hash)?
+                    .ok_or_else(|| anyhow!("Value {} not found", hex::encode(value_hash)))?;
+                Ok((key.to_vec(), value.to_vec()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort();
+
+        let block_key = StateKey::Block.to_bytes();
+        let block = match entries.iter().find(|(key, _)| key == &block_key) {
+            Some((_, value)) => BlockInfo::try_from_slice(value)?,
+            None => BlockInfo::default(),
+        };
+
+        Ok(Snapshot {
+            root,
+            block,
+            entries,
+        })
+    }
+
+    /// Writes the snapshot of the state root to the file.
+    pub fn export_snapshot(&self, root: Hash, path: &Path) -> Result<Snapshot> {
+        let snapshot = self.snapshot(root)?;
+
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend(SNAPSHOT_VERSION.to_le_bytes());
+        bytes.extend(snapshot.try_to_vec()?);
+        let checksum = sha256(&bytes);
+        bytes.extend(checksum);
+
+        std::fs::write(path, bytes).with_context(|| format!("Can't write snapshot {:?}", path))?;
+        info!(
+            root = hex::encode(root),
+            height = snapshot.block.height,
+            entries = snapshot.entries.len(),
+            "Snapshot exported"
+        );
+
+        Ok(snapshot)
+    }
+
+    /// Loads the snapshot file into an empty state and returns its root.
+    ///
+    /// Fails if the file is corrupted or the imported state doesn't match the snapshot root.
+    pub fn import_snapshot(&mut self, path: &Path) -> Result<Hash> {
+        if self.root != EMPTY_HASH {
+            bail!("State is already initialized");
+        }
+
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Can't read snapshot {:?}", path))?;
+        let header_len = SNAPSHOT_MAGIC.len() + 4;
+        if bytes.len() < header_len + 32 || &bytes[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+            bail!("{:?} is not a snapshot", path);
+        }
+
+        let version = u32::from_le_bytes(bytes[SNAPSHOT_MAGIC.len()..header_len].try_into()?);
+        if version != SNAPSHOT_VERSION {
+            bail!(
+                "Snapshot version {} is not supported, expected {}",
+                version,
+                SNAPSHOT_VERSION
+            );
+        }
+
+        let (content, checksum) = bytes.split_at(bytes.len() - 32);
+        if sha256(content) != checksum {
+            bail!("Snapshot checksum mismatch");
+        }
+
+        let snapshot = Snapshot::try_from_slice(&content[header_len..])?;
+        for (key, value) in snapshot.entries {
+            self.changes.insert(key, Some(value));
+        }
+
+        let root = self.commit()?;
+        if root != snapshot.root {
+            bail!(
+                "Imported state root {} doesn't match the snapshot root {}",
+                hex::encode(root),
+                hex::encode(snapshot.root)
+            );
+        }
+
+        info!(
+            root = hex::encode(root),
+            height = snapshot.block.height,
+            "Snapshot imported"
+        );
+        Ok(root)
+    }
+}
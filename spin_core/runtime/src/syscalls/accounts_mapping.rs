@@ -1,4 +1,5 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use borsh::BorshSerialize;
 use risc0_zkvm::{serde::to_vec, Syscall};
 use tracing::{debug, span, Level};
 
@@ -35,10 +36,10 @@ impl Syscall for AccountsMappingHandler {
         let buf_len = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A4);
         let from_guest = syscall_ctx.load_region(buf_ptr, buf_len);
 
-        let account_id = AccountId::new(String::from_utf8(from_guest).unwrap());
+        let account_id = AccountId::try_from(String::from_utf8(from_guest)?)?;
         debug!(of_account_id=?account_id);
 
-        let evm_address = ExecutionContext::get_account_evm_address(account_id);
+        let evm_address = ctx.state().read().unwrap().evm_address(&account_id)?;
         debug!(evm_address=?evm_address);
 
         let output: Vec<u32> = to_vec(&evm_address.to_fixed_bytes()).unwrap();
@@ -47,3 +48,50 @@ impl Syscall for AccountsMappingHandler {
         Ok((0, 0))
     }
 }
+
+pub struct ReverseAccountsMappingHandler {
+    context: Arc<RwLock<ExecutionContext>>,
+}
+
+impl ReverseAccountsMappingHandler {
+    pub fn new(context: Arc<RwLock<ExecutionContext>>) -> Self {
+        Self { context }
+    }
+}
+
+impl Syscall for ReverseAccountsMappingHandler {
+    fn syscall(
+        &mut self,
+        _syscall: &str,
+        syscall_ctx: &mut dyn risc0_zkvm::SyscallContext,
+        to_guest: &mut [u32],
+    ) -> Result<(u32, u32)> {
+        let span = span!(Level::DEBUG, "reverse_accounts_mapping handler");
+        let _enter = span.enter();
+
+        let ctx = self.context.write().unwrap();
+        debug!(from_contract=?ctx.contract());
+
+        let buf_ptr = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A3);
+        let buf_len = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A4);
+        let from_guest = syscall_ctx.load_region(buf_ptr, buf_len);
+
+        if from_guest.len() != 20 {
+            bail!("Invalid EVM address length {}", from_guest.len());
+        }
+        let evm_address = eth_primitive_types::H160::from_slice(&from_guest);
+        debug!(of_evm_address=?evm_address);
+
+        let account_id = ctx
+            .state()
+            .read()
+            .unwrap()
+            .account_by_evm_address(&evm_address)?;
+        debug!(account_id=?account_id);
+
+        let output: Vec<u32> = to_vec(&account_id.try_to_vec()?).unwrap();
+
+        to_guest[0..output.len()].copy_from_slice(&output);
+        Ok((0, 0))
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use borsh::BorshDeserialize;
+use risc0_zkvm::Syscall;
+use tracing::debug;
+
+use std::sync::{Arc, RwLock};
+
+use spin_primitives::syscalls::AsyncCallRequest;
+
+use crate::context::ExecutionContext;
+
+pub struct AsyncCallHandler {
+    context: Arc<RwLock<ExecutionContext>>,
+}
+
+impl AsyncCallHandler {
+    pub fn new(context: Arc<RwLock<ExecutionContext>>) -> Self {
+        Self { context }
+    }
+}
+
+impl Syscall for AsyncCallHandler {
+    fn syscall(
+        &mut self,
+        _syscall: &str,
+        syscall_ctx: &mut dyn risc0_zkvm::SyscallContext,
+        _to_guest: &mut [u32],
+    ) -> Result<(u32, u32)> {
+        let mut ctx = self.context.write().unwrap();
+
+        let buf_ptr = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A3);
+        let buf_len = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A4);
+        let from_guest = syscall_ctx.load_region(buf_ptr, buf_len);
+
+        let request = AsyncCallRequest::try_from_slice(&from_guest)?;
+        debug!(
+            from_contract = ?ctx.contract(),
+            to_contract = ?request.call.account,
+            method = request.call.function_call.method,
+            callback = ?request.callback.as_ref().map(|callback| &callback.method),
+            "Scheduling asynchronous call"
+        );
+
+        ctx.schedule_call(request.call, request.callback)?;
+
+        Ok((0, 0))
+    }
+}
@@ -1,20 +1,69 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use borsh::BorshSerialize;
 use risc0_zkvm::{serde::to_vec, Syscall};
-use tracing::debug;
+use tracing::{debug, warn};
 
 use std::sync::{Arc, RwLock};
 
-use spin_primitives::ContractCall;
+use spin_primitives::{CallError, ContractCall};
 
 use crate::{context::ExecutionContext, executor};
 
 pub struct CrossContractCallHandler {
     context: Arc<RwLock<ExecutionContext>>,
+    /// Whether a failure of the callee is returned to the caller instead of failing it.
+    fallible: bool,
 }
 
 impl CrossContractCallHandler {
     pub fn new(context: Arc<RwLock<ExecutionContext>>) -> Self {
-        Self { context }
+        Self {
+            context,
+            fallible: false,
+        }
+    }
+
+    pub fn new_fallible(context: Arc<RwLock<ExecutionContext>>) -> Self {
+        Self {
+            context,
+            fallible: true,
+        }
+    }
+}
+
+/// Executes the call on top of a state checkpoint, which is reverted if the callee fails.
+///
+/// Returns the journal of the callee.
+fn execute_call(
+    origin_ctx: &mut ExecutionContext,
+    call: ContractCall,
+) -> Result<Vec<u8>, CallError> {
+    let ccc_ctx = origin_ctx
+        .cross_contract_call(call)
+        .map_err(|e| executor::call_error(&e))?;
+
+    origin_ctx.state().write().unwrap().checkpoint();
+
+    match executor::execute(ccc_ctx.clone()) {
+        Ok(ccc_session) => {
+            origin_ctx.state().write().unwrap().discard_checkpoint();
+
+            let ccc_journal = ccc_session.journal.clone();
+            ccc_ctx.write().unwrap().set_execution_session(ccc_session);
+            Ok(ccc_journal)
+        }
+        Err(e) => {
+            origin_ctx.state().write().unwrap().revert_to_checkpoint();
+
+            let error = executor::call_error(&e);
+            ccc_ctx.write().unwrap().set_failed(error.clone());
+            warn!(
+                to_contract = ?ccc_ctx.read().unwrap().contract(),
+                ?error,
+                "Cross contract call failed"
+            );
+            Err(error)
+        }
     }
 }
 
@@ -26,27 +75,84 @@ impl Syscall for CrossContractCallHandler {
         to_guest: &mut [u32],
     ) -> Result<(u32, u32)> {
         let mut origin_ctx = self.context.write().unwrap();
-        debug!(from_contract=?origin_ctx.contract(), "handling syscall for cross contract call");
-        origin_ctx.set_gas_usage(syscall_ctx.get_cycle().try_into().unwrap());
+        debug!(
+            from_contract = ?origin_ctx.contract(),
+            fallible = self.fallible,
+            "handling syscall for cross contract call"
+        );
 
         let buf_ptr = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A3);
         let buf_len = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A4);
         let from_guest = syscall_ctx.load_region(buf_ptr, buf_len);
 
-        let call = ContractCall::try_from_bytes(from_guest).expect("Invalid contract call");
+        let call = ContractCall::try_from_bytes(from_guest)?;
 
-        let ccc_ctx = origin_ctx.cross_contract_call(call).unwrap();
+        let result = execute_call(&mut origin_ctx, call);
 
-        let ccc_session = executor::execute(ccc_ctx.clone()).unwrap(); // TODO: handle error
-        let ccc_journal = ccc_session.journal.clone();
-        {
-            let mut ccc_ctx = ccc_ctx.write().unwrap();
-            ccc_ctx.set_execution_session(ccc_session);
+        // Only failures committed by the callee have a receipt. The caller fails with the others,
+        // so that a caller handling them never makes a state transition which can't be proven.
+        if let Err(error) = &result {
+            let proven = origin_ctx
+                .cross_contract_calls()
+                .last()
+                .map_or(false, |call| {
+                    call.read().unwrap().execution_session().is_some()
+                });
+            if !proven {
+                return Err(error.clone().into());
+            }
         }
 
-        let output: Vec<u32> = to_vec(&ccc_journal).unwrap();
+        let output: Vec<u32> = if self.fallible {
+            to_vec(&result.try_to_vec()?)?
+        } else {
+            to_vec(&result?)?
+        };
+
+        // The length is returned either way, a response which doesn't fit in the buffer of the
+        // guest is kept for the CALL_RESPONSE syscall.
+        let len = output.len();
+        if len <= to_guest.len() {
+            to_guest[..len].copy_from_slice(&output);
+        } else {
+            origin_ctx.set_call_response(output);
+        }
+        Ok((len.try_into()?, 0))
+    }
+}
+
+/// Returns the response of the last cross-contract call which didn't fit in the buffer of the
+/// guest.
+pub struct CallResponseHandler {
+    context: Arc<RwLock<ExecutionContext>>,
+}
+
+impl CallResponseHandler {
+    pub fn new(context: Arc<RwLock<ExecutionContext>>) -> Self {
+        Self { context }
+    }
+}
+
+impl Syscall for CallResponseHandler {
+    fn syscall(
+        &mut self,
+        _syscall: &str,
+        _syscall_ctx: &mut dyn risc0_zkvm::SyscallContext,
+        to_guest: &mut [u32],
+    ) -> Result<(u32, u32)> {
+        let mut ctx = self.context.write().unwrap();
+        let response = ctx
+            .take_call_response()
+            .ok_or_else(|| anyhow!("No pending response of a cross-contract call"))?;
+        if response.len() > to_guest.len() {
+            bail!(
+                "Response of a cross-contract call is {} words, the buffer is {}",
+                response.len(),
+                to_guest.len()
+            );
+        }
 
-        to_guest[0..output.len()].copy_from_slice(&output);
-        Ok((0, 0))
+        to_guest[..response.len()].copy_from_slice(&response);
+        Ok((response.len().try_into()?, 0))
     }
 }
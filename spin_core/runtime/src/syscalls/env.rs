@@ -26,7 +26,7 @@ impl Syscall for GetEnvCallHandler {
         let context = self.context.write().unwrap();
         debug!(from_contract=?context.contract(), "handling syscall for env loading");
 
-        let env = context.call_env();
+        let env = context.call_env()?;
 
         let output = to_vec(&env.into_bytes()).unwrap();
         to_guest[0..output.len()].copy_from_slice(&output);
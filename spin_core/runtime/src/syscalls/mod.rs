@@ -1,4 +1,6 @@
 pub mod accounts_mapping;
+pub mod async_call;
 pub mod cross_contract;
 pub mod env;
+pub mod schedule;
 pub mod storage;
@@ -1,8 +1,10 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use borsh::{BorshDeserialize, BorshSerialize};
 use risc0_zkvm::sha::rust_crypto::{Digest, Sha256};
 use risc0_zkvm::{serde::to_vec, Syscall};
-use spin_primitives::syscalls::{GetStorageResponse, SetStorageRequest};
+use spin_primitives::syscalls::{
+    GetStorageResponse, IterStoragePrefixRequest, IterStoragePrefixResponse, SetStorageRequest,
+};
 use tracing::{debug, span, Level};
 
 use std::sync::{Arc, RwLock};
@@ -33,30 +35,28 @@ impl Syscall for GetStorageCallHandler {
         let span = span!(Level::DEBUG, "get_storage call handler");
         let _enter = span.enter();
 
-        let context = self.context.write().unwrap();
+        let mut context = self.context.write().unwrap();
 
         let buf_ptr = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A3);
         let buf_len = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A4);
         let from_guest = syscall_ctx.load_region(buf_ptr, buf_len);
-        let key = String::from_utf8(from_guest).unwrap();
+        let key = String::from_utf8(from_guest)?;
 
-        // TODO: don't use files as contract state storage xD
-        let state: Vec<u8> = std::fs::read(format!(
-            "./state/storage/{}.{}",
-            key,
-            context.contract().to_string()
-        ))
-        .unwrap_or_else(|e| match e.kind() {
-            std::io::ErrorKind::NotFound => {
+        let state = context
+            .state()
+            .read()
+            .unwrap()
+            .get_storage(context.contract(), &key)?
+            .unwrap_or_else(|| {
                 debug!(
                     "No state found for key {:?} in {:?}, creating new",
                     key,
                     context.contract()
                 );
                 Vec::new()
-            }
-            _ => todo!(),
-        });
+            });
+
+        context.record_storage_read(key.len() + state.len());
 
         // tracing::warn!("state: {:?}", state);
 
@@ -69,11 +69,18 @@ impl Syscall for GetStorageCallHandler {
             state,
         };
 
-        let response_bytes = BorshSerialize::try_to_vec(&response).unwrap();
+        let response_bytes = BorshSerialize::try_to_vec(&response)?;
 
         debug!(contract=?context.contract(), key=?key, hash = bytes_to_hex_string(hash.as_slice()), "Loading storage");
 
-        let output = to_vec(&response_bytes).unwrap();
+        let output = to_vec(&response_bytes)?;
+        if output.len() > to_guest.len() {
+            bail!(
+                "Value of {:?} in {} doesn't fit the guest buffer",
+                key,
+                context.contract()
+            );
+        }
         to_guest[0..output.len()].copy_from_slice(&output);
 
         Ok((0, 0))
@@ -100,32 +107,149 @@ impl risc0_zkvm::Syscall for SetStorageCallHandler {
         let span = span!(Level::DEBUG, "set_storage call handler");
         let _enter = span.enter();
 
-        let context = self.context.write().unwrap();
+        let mut context = self.context.write().unwrap();
 
         let buf_ptr = ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A3);
         let buf_len = ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A4);
         let from_guest = ctx.load_region(buf_ptr, buf_len);
 
-        let request: SetStorageRequest =
-            BorshDeserialize::deserialize(&mut from_guest.as_slice()).unwrap();
+        let request: SetStorageRequest = BorshDeserialize::deserialize(&mut from_guest.as_slice())?;
 
         let algorithm = &mut Sha256::default();
         algorithm.update(request.state.clone());
         let hash2 = algorithm.finalize_reset();
-        assert_eq!(request.hash, hash2.as_slice());
+        if request.hash != hash2.as_slice() {
+            bail!("Hash of the value of {:?} doesn't match", request.key);
+        }
+
+        context.record_storage_write(request.key.len() + request.state.len());
 
         debug!(contract=?context.contract(), key=?request.key, new_hash = bytes_to_hex_string(hash2.as_slice()), "Updating storage");
 
-        // TODO: don't use files as contract state storage xD
-        std::fs::write(
-            format!(
-                "./state/storage/{}.{}",
-                request.key,
-                context.contract().to_string()
-            ),
+        context.state().write().unwrap().set_storage(
+            context.contract().clone(),
+            request.key,
             request.state,
-        )
-        .unwrap();
+            context.storage_payer(),
+        )?;
+
+        Ok((0, 0))
+    }
+}
+
+pub struct RemoveStorageCallHandler {
+    context: Arc<RwLock<ExecutionContext>>,
+}
+
+impl RemoveStorageCallHandler {
+    pub fn new(context: Arc<RwLock<ExecutionContext>>) -> Self {
+        Self { context }
+    }
+}
+
+impl Syscall for RemoveStorageCallHandler {
+    fn syscall(
+        &mut self,
+        _syscall: &str,
+        syscall_ctx: &mut dyn risc0_zkvm::SyscallContext,
+        _to_guest: &mut [u32],
+    ) -> Result<(u32, u32)> {
+        let span = span!(Level::DEBUG, "remove_storage call handler");
+        let _enter = span.enter();
+
+        let mut context = self.context.write().unwrap();
+
+        let buf_ptr = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A3);
+        let buf_len = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A4);
+        let from_guest = syscall_ctx.load_region(buf_ptr, buf_len);
+        let key = String::from_utf8(from_guest)?;
+        context.record_storage_write(key.len());
+
+        debug!(contract = ?context.contract(), key = ?key, "Removing storage");
+
+        context.state().write().unwrap().remove_storage(
+            context.contract().clone(),
+            key,
+            context.storage_payer(),
+        )?;
+
+        Ok((0, 0))
+    }
+}
+
+pub struct IterStoragePrefixCallHandler {
+    context: Arc<RwLock<ExecutionContext>>,
+}
+
+impl IterStoragePrefixCallHandler {
+    pub fn new(context: Arc<RwLock<ExecutionContext>>) -> Self {
+        Self { context }
+    }
+}
+
+impl Syscall for IterStoragePrefixCallHandler {
+    fn syscall(
+        &mut self,
+        _syscall: &str,
+        syscall_ctx: &mut dyn risc0_zkvm::SyscallContext,
+        to_guest: &mut [u32],
+    ) -> Result<(u32, u32)> {
+        let span = span!(Level::DEBUG, "iter_storage_prefix call handler");
+        let _enter = span.enter();
+
+        let mut context = self.context.write().unwrap();
+
+        let buf_ptr = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A3);
+        let buf_len = syscall_ctx.load_register(risc0_zkvm_platform::syscall::reg_abi::REG_A4);
+        let from_guest = syscall_ctx.load_region(buf_ptr, buf_len);
+
+        let request = IterStoragePrefixRequest::try_from_slice(&from_guest)?;
+
+        let (mut entries, mut cursor) = context.state().read().unwrap().storage_page(
+            context.contract(),
+            &request.prefix,
+            request.cursor.as_deref(),
+            request.limit as usize,
+        )?;
+
+        // Shorten the page until it fits the guest buffer, the rest goes to the next page.
+        let output = loop {
+            let algorithm = &mut Sha256::default();
+            algorithm.update(entries.try_to_vec()?);
+            let hash = algorithm.finalize_reset();
+
+            let response = IterStoragePrefixResponse {
+                hash: hash.into(),
+                entries: entries.clone(),
+                cursor: cursor.clone(),
+            };
+            let output = to_vec(&response.try_to_vec()?)?;
+            if output.len() <= to_guest.len() {
+                break output;
+            }
+
+            match entries.pop() {
+                Some((key, _)) => cursor = Some(key),
+                None => bail!("Storage page doesn't fit the guest buffer"),
+            }
+        };
+
+        context.record_storage_read(
+            entries
+                .iter()
+                .map(|(key, value)| key.len() + value.len())
+                .sum(),
+        );
+
+        debug!(
+            contract = ?context.contract(),
+            prefix = request.prefix,
+            entries = entries.len(),
+            ?cursor,
+            "Iterating storage"
+        );
+
+        to_guest[0..output.len()].copy_from_slice(&output);
 
         Ok((0, 0))
     }
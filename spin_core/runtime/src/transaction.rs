@@ -0,0 +1,223 @@
+use anyhow::{anyhow, bail, Result};
+use ed25519_dalek::{Verifier, VerifyingKey};
+use spin_primitives::{
+    AccessKey, AccessKeyPermission, Account, AccountId, Action, FunctionCallPermission,
+    SignedTransaction, Transaction,
+};
+use tracing::debug;
+
+use std::collections::BTreeSet;
+
+use crate::state::State;
+
+/// Checks the signature of the transaction and that its access key may sign it.
+///
+/// Returns the access key, to be passed to [`consume_access_key`] after execution.
+pub fn verify(state: &State, signed: &SignedTransaction) -> Result<AccessKey> {
+    let transaction = &signed.transaction;
+
+    VerifyingKey::from_bytes(&transaction.public_key.0)?
+        .verify(
+            &transaction.hash(),
+            &ed25519_dalek::Signature::from_bytes(&signed.signature.0),
+        )
+        .map_err(|_| anyhow!("Invalid signature of transaction by {}", transaction.signer))?;
+
+    let key = state
+        .get_access_key(&transaction.signer, &transaction.public_key)?
+        .ok_or_else(|| {
+            anyhow!(
+                "Access key {} of {} not found",
+                hex::encode(transaction.public_key.0),
+                transaction.signer
+            )
+        })?;
+
+    if transaction.nonce <= key.nonce {
+        bail!(
+            "Transaction nonce {} must be greater than the access key nonce {}",
+            transaction.nonce,
+            key.nonce
+        );
+    }
+
+    let attached_gas = transaction
+        .attached_gas()
+        .ok_or_else(|| anyhow!("Attached gas of the transaction overflows"))?;
+
+    if let AccessKeyPermission::FunctionCall(permission) = &key.permission {
+        for action in &transaction.actions {
+            let call = match action {
+                Action::FunctionCall(call) => call,
+                _ => bail!("Function call access key can only sign function calls"),
+            };
+            if !permission.allows(call) {
+                bail!(
+                    "Access key doesn't allow calling {} of {}",
+                    call.function_call.method,
+                    call.account
+                );
+            }
+        }
+
+        if let Some(allowance) = permission.allowance {
+            if attached_gas > allowance {
+                bail!(
+                    "Attached gas {} exceeds the access key allowance {}",
+                    attached_gas,
+                    allowance
+                );
+            }
+        }
+    }
+
+    Ok(key)
+}
+
+/// Stores the transaction nonce in the access key and charges its allowance with the used gas.
+///
+/// Does nothing if the transaction deleted its own key.
+pub fn consume_access_key(
+    state: &mut State,
+    transaction: &Transaction,
+    mut key: AccessKey,
+    gas_used: u64,
+) -> Result<()> {
+    if state
+        .get_access_key(&transaction.signer, &transaction.public_key)?
+        .is_none()
+    {
+        return Ok(());
+    }
+
+    key.nonce = transaction.nonce;
+    if let AccessKeyPermission::FunctionCall(FunctionCallPermission {
+        allowance: Some(allowance),
+        ..
+    }) = &mut key.permission
+    {
+        *allowance = allowance.saturating_sub(gas_used);
+    }
+
+    state.set_access_key(
+        transaction.signer.clone(),
+        transaction.public_key.clone(),
+        &key,
+    );
+    Ok(())
+}
+
+/// Applies an action other than a function call on behalf of the signer.
+///
+/// `created` collects the accounts created by earlier actions of the same transaction, which the
+/// signer may deploy code to.
+pub fn apply_action(
+    state: &mut State,
+    signer: &AccountId,
+    action: &Action,
+    created: &mut BTreeSet<AccountId>,
+) -> Result<()> {
+    match action {
+        Action::CreateAccount {
+            account,
+            public_key,
+        } => {
+            debug!(?signer, ?account, "Creating account");
+            state.create_sub_account(signer, account.clone(), &Account::default())?;
+            if let Some(public_key) = public_key {
+                state.set_access_key(
+                    account.clone(),
+                    public_key.clone(),
+                    &AccessKey::full_access(),
+                );
+            }
+            created.insert(account.clone());
+        }
+        Action::DeployContract { account, code } => {
+            if account != signer && !created.contains(account) {
+                bail!(
+                    "{} can only deploy to itself or accounts it created, not {}",
+                    signer,
+                    account
+                );
+            }
+            if state.get_account(account)?.is_none() {
+                bail!("Account {} doesn't exist", account);
+            }
+            state.deploy_contract(account.clone(), code.clone());
+        }
+        Action::Transfer { receiver, amount } => {
+            let mut sender_account = state
+                .get_account(signer)?
+                .ok_or_else(|| anyhow!("Account {} doesn't exist", signer))?;
+            let mut receiver_account = state
+                .get_account(receiver)?
+                .ok_or_else(|| anyhow!("Account {} doesn't exist", receiver))?;
+
+            // Balance locked for storage stays with the account.
+            let available = sender_account.available_balance(state.storage_byte_cost()?);
+            if available < *amount {
+                bail!(
+                    "Available balance {} of {} is less than {}",
+                    available,
+                    signer,
+                    amount
+                );
+            }
+
+            debug!(?signer, ?receiver, amount, "Transferring");
+            if receiver != signer {
+                sender_account.balance -= amount;
+                receiver_account.balance += amount;
+                state.set_account(signer.clone(), &sender_account);
+                state.set_account(receiver.clone(), &receiver_account);
+            }
+        }
+        Action::AddKey {
+            public_key,
+            permission,
+        } => {
+            if state.get_access_key(signer, public_key)?.is_some() {
+                bail!(
+                    "Access key {} of {} already exists",
+                    hex::encode(public_key.0),
+                    signer
+                );
+            }
+
+            debug!(
+                ?signer,
+                public_key = hex::encode(public_key.0),
+                ?permission,
+                "Adding access key"
+            );
+            state.set_access_key(
+                signer.clone(),
+                public_key.clone(),
+                &AccessKey {
+                    nonce: 0,
+                    permission: permission.clone(),
+                },
+            );
+        }
+        Action::DeleteKey { public_key } => {
+            if state.get_access_key(signer, public_key)?.is_none() {
+                bail!(
+                    "Access key {} of {} not found",
+                    hex::encode(public_key.0),
+                    signer
+                );
+            }
+
+            debug!(
+                ?signer,
+                public_key = hex::encode(public_key.0),
+                "Deleting access key"
+            );
+            state.remove_access_key(signer.clone(), public_key.clone());
+        }
+        Action::FunctionCall(_) => bail!("Function calls are executed by the executor"),
+    }
+
+    Ok(())
+}
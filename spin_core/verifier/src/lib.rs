@@ -0,0 +1,394 @@
+use anyhow::{anyhow, bail, Context, Result};
+use risc0_zkvm::{sha::Digest, SessionReceipt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use spin_primitives::{
+    proof::{sha256, Hash},
+    AccountId, CallEnv, ExecutionCommittment, FunctionCall,
+};
+
+use std::{collections::BTreeMap, path::Path};
+
+const BUNDLE_MAGIC: &[u8; 8] = b"SPINPROF";
+const BLOCK_MAGIC: &[u8; 8] = b"SPINBLCK";
+
+/// Version of the bundle format, bundles of other versions are rejected.
+pub const BUNDLE_VERSION: u32 = 4;
+
+/// Environment variable enabling dev mode, in which receipts are fake and only accepted by
+/// verifiers in dev mode too.
+pub const DEV_MODE_ENV: &str = "SPIN_DEV_MODE";
+
+/// Proof of a contract call and of all of its cross-contract calls, checkable without the state.
+///
+/// The file holds the magic bytes, the version as little-endian `u32`, the bundle encoded with
+/// the zkVM serde format and the SHA-256 checksum of everything before it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProofBundle {
+    pub call: ProvenCall,
+}
+
+/// Proofs of all function calls executed in a block, in the order they were executed, with the
+/// state roots before and after the block.
+///
+/// Receipts don't commit to the state, so the roots are the ones stated by the node: the bundle
+/// proves which calls ran in the block and what they returned, not the state transition. Actions
+/// other than function calls aren't proven. The file has the same layout as a [`ProofBundle`],
+/// under other magic bytes.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlockProof {
+    pub height: u64,
+    /// State root at the end of the previous block.
+    pub previous_root: Hash,
+    /// State root at the end of the block.
+    pub root: Hash,
+    pub bundles: Vec<ProofBundle>,
+}
+
+/// Receipt of a call, whose journal is its Borsh-encoded [`ExecutionCommittment`].
+#[derive(Serialize, Deserialize, Debug)]
+pub enum CallReceipt {
+    Session(SessionReceipt),
+    /// Receipt made in dev mode without proving, which only carries the journal of the call.
+    Fake {
+        journal: Vec<u8>,
+    },
+}
+
+impl CallReceipt {
+    pub fn journal(&self) -> &[u8] {
+        match self {
+            CallReceipt::Session(receipt) => &receipt.journal,
+            CallReceipt::Fake { journal } => journal,
+        }
+    }
+
+    pub fn is_fake(&self) -> bool {
+        matches!(self, CallReceipt::Fake { .. })
+    }
+}
+
+/// Proven call with its environment and the proofs of its cross-contract calls.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProvenCall {
+    /// Environment the call ran in, with its signer, caller, contract and attached gas.
+    pub env: CallEnv,
+    pub call: FunctionCall,
+    /// Image ID of the contract code.
+    pub image_id: Digest,
+    pub receipt: CallReceipt,
+    /// Cross-contract calls of the call, in the order they were made.
+    pub calls: Vec<CallProof>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum CallProof {
+    Proven(ProvenCall),
+    /// Call which failed, and whose failure was returned to the caller. Its receipt proves the
+    /// failure: its journal commits to the error instead of an output.
+    Failed(ProvenCall),
+}
+
+impl ProvenCall {
+    pub fn contract(&self) -> &AccountId {
+        &self.env.contract
+    }
+
+    pub fn committment(&self) -> Result<ExecutionCommittment> {
+        ExecutionCommittment::try_from_bytes(self.receipt.journal().to_vec())
+            .with_context(|| format!("Invalid journal of the call to {}", self.contract()))
+    }
+
+    /// Verifies the receipt against the image ID, that it was made by the call in its
+    /// environment, that the call succeeded, and that the cross-contract calls committed to by
+    /// the call are exactly the proven ones.
+    ///
+    /// Fake receipts are only accepted in dev mode, and then only their journals are checked.
+    pub fn verify(&self, dev_mode: bool) -> Result<()> {
+        self.verify_outcome(dev_mode, false)
+    }
+
+    /// Verifies the call, which must have failed if `failed` is set and succeeded otherwise.
+    fn verify_outcome(&self, dev_mode: bool, failed: bool) -> Result<()> {
+        match &self.receipt {
+            CallReceipt::Session(receipt) => receipt.verify(self.image_id).map_err(|e| {
+                anyhow!("Invalid receipt of the call to {}: {}", self.contract(), e)
+            })?,
+            CallReceipt::Fake { .. } if dev_mode => {}
+            CallReceipt::Fake { .. } => bail!(
+                "Receipt of the call to {} is fake, it is only accepted in dev mode",
+                self.contract()
+            ),
+        }
+
+        let committment = self.committment()?;
+        if !committment.is_bound_to(&self.env, &self.call) {
+            bail!(
+                "Receipt of the call to {} wasn't made by the call",
+                self.contract()
+            );
+        }
+
+        match (&committment.error, failed) {
+            (None, true) => bail!(
+                "Call to {} is proven as failed, but its receipt shows it succeeded",
+                self.contract()
+            ),
+            (Some(error), false) => bail!("Call to {} failed: {}", self.contract(), error),
+            _ => {}
+        }
+
+        let committed = committment.cross_calls_hashes;
+        let proven = self
+            .calls
+            .iter()
+            .map(CallProof::response_hash)
+            .collect::<Result<Vec<_>>>()?;
+        if committed != proven {
+            bail!(
+                "Call to {} committed to {} cross-contract calls, which don't match the {} proven",
+                self.contract(),
+                committed.len(),
+                proven.len()
+            );
+        }
+
+        for call_proof in self.calls.iter() {
+            let call = call_proof.call();
+            if call.env.caller != self.env.contract || call.env.signer != self.env.signer {
+                bail!(
+                    "Call to {} wasn't made by {} for {}",
+                    call.contract(),
+                    self.contract(),
+                    self.env.signer
+                );
+            }
+            call.verify_outcome(dev_mode, matches!(call_proof, CallProof::Failed(_)))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the receipt of the call or of one of its cross-contract calls is fake.
+    pub fn is_fake(&self) -> bool {
+        self.receipt.is_fake() || self.calls.iter().any(|call| call.call().is_fake())
+    }
+
+    /// Collects the image ID of every call in the tree, failing if a contract ran two images.
+    fn collect_image_ids(&self, image_ids: &mut BTreeMap<AccountId, Digest>) -> Result<()> {
+        match image_ids.insert(self.contract().clone(), self.image_id) {
+            Some(image_id) if image_id != self.image_id => bail!(
+                "Contract {} ran both image {} and image {}",
+                self.contract(),
+                image_id,
+                self.image_id
+            ),
+            _ => {}
+        }
+        for call in self.calls.iter() {
+            call.call().collect_image_ids(image_ids)?;
+        }
+        Ok(())
+    }
+}
+
+impl CallProof {
+    pub fn call(&self) -> &ProvenCall {
+        match self {
+            CallProof::Proven(call) | CallProof::Failed(call) => call,
+        }
+    }
+
+    /// Hash the caller commits to for this call: of the journal of a successful call, or of the
+    /// Borsh-encoded error of a failed one.
+    fn response_hash(&self) -> Result<[u8; 32]> {
+        Ok(match self {
+            CallProof::Proven(call) => {
+                ExecutionCommittment::cross_call_hash(Ok(call.receipt.journal()))
+            }
+            CallProof::Failed(call) => {
+                let error = call.committment()?.error.ok_or_else(|| {
+                    anyhow!(
+                        "Call to {} is proven as failed, but succeeded",
+                        call.contract()
+                    )
+                })?;
+                ExecutionCommittment::cross_call_hash(Err(&error))
+            }
+        })
+    }
+}
+
+impl ProofBundle {
+    /// Verifies every receipt of the call tree and how the calls are linked together, and that
+    /// every contract ran a single image.
+    ///
+    /// The image IDs are taken from the bundle, compare them with [`ProofBundle::image_ids`] to
+    /// make sure the expected code was run. Fake receipts are rejected unless `dev_mode` is set.
+    pub fn verify(&self, dev_mode: bool) -> Result<()> {
+        self.call.verify(dev_mode)?;
+        self.image_ids()?;
+        Ok(())
+    }
+
+    /// Image IDs of all contracts which ran in the call tree, failing if a contract ran two
+    /// different images.
+    pub fn image_ids(&self) -> Result<BTreeMap<AccountId, Digest>> {
+        let mut image_ids = BTreeMap::new();
+        self.call.collect_image_ids(&mut image_ids)?;
+        Ok(image_ids)
+    }
+
+    /// Encodes the bundle in the file format.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        encode(BUNDLE_MAGIC, self)
+    }
+
+    /// Decodes a bundle in the file format, failing if it is corrupted or of another version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        decode(BUNDLE_MAGIC, "proof bundle", bytes)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_bytes()?)
+            .with_context(|| format!("Can't write proof bundle {:?}", path))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Can't read proof bundle {:?}", path))?;
+        Self::from_bytes(&bytes).with_context(|| format!("Invalid proof bundle {:?}", path))
+    }
+}
+
+impl BlockProof {
+    /// Verifies the bundle of every call of the block, and that every call ran in the block.
+    ///
+    /// Fake receipts are rejected unless `dev_mode` is set.
+    pub fn verify(&self, dev_mode: bool) -> Result<()> {
+        for (index, bundle) in self.bundles.iter().enumerate() {
+            bundle
+                .verify(dev_mode)
+                .with_context(|| format!("Invalid proof bundle {} of the block", index))?;
+            if bundle.call.env.block.height != self.height {
+                bail!(
+                    "Call {} of block {} ran in block {}",
+                    index,
+                    self.height,
+                    bundle.call.env.block.height
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a receipt of one of the calls is fake.
+    pub fn is_fake(&self) -> bool {
+        self.bundles.iter().any(|bundle| bundle.call.is_fake())
+    }
+
+    /// Encodes the block proof in the file format.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        encode(BLOCK_MAGIC, self)
+    }
+
+    /// Decodes a block proof in the file format, failing if it is corrupted or of another
+    /// version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        decode(BLOCK_MAGIC, "block proof", bytes)
+    }
+
+    /// Whether the bytes start like a block proof, rather than the proof bundle of a call.
+    pub fn is_block_proof(bytes: &[u8]) -> bool {
+        bytes.starts_with(BLOCK_MAGIC)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_bytes()?)
+            .with_context(|| format!("Can't write block proof {:?}", path))
+    }
+}
+
+/// Encodes the value after the magic bytes and the version, followed by the checksum.
+fn encode(magic: &[u8; 8], value: &impl Serialize) -> Result<Vec<u8>> {
+    let mut bytes = magic.to_vec();
+    bytes.extend(BUNDLE_VERSION.to_le_bytes());
+    bytes.extend(
+        risc0_zkvm::serde::to_vec(value)?
+            .into_iter()
+            .flat_map(u32::to_le_bytes),
+    );
+    let checksum = sha256(&bytes);
+    bytes.extend(checksum);
+    Ok(bytes)
+}
+
+/// Decodes a value encoded by [`encode`] with the same magic bytes, failing if it is corrupted
+/// or of another version; `kind` names the file in errors.
+fn decode<T: DeserializeOwned>(magic: &[u8; 8], kind: &str, bytes: &[u8]) -> Result<T> {
+    let header_len = magic.len() + 4;
+    if bytes.len() < header_len + 32 || &bytes[..magic.len()] != magic {
+        bail!("Not a {}", kind);
+    }
+
+    let version = u32::from_le_bytes(bytes[magic.len()..header_len].try_into()?);
+    if version != BUNDLE_VERSION {
+        bail!(
+            "Version {} of the {} is not supported, expected {}",
+            version,
+            kind,
+            BUNDLE_VERSION
+        );
+    }
+
+    let (content, checksum) = bytes.split_at(bytes.len() - 32);
+    if sha256(content) != checksum {
+        bail!("Checksum mismatch of the {}", kind);
+    }
+
+    let body = &content[header_len..];
+    if body.len() % 4 != 0 {
+        bail!("The {} is truncated", kind);
+    }
+    let words = body
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect::<Vec<_>>();
+
+    Ok(risc0_zkvm::serde::from_slice(&words)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_proof() -> BlockProof {
+        BlockProof {
+            height: 7,
+            previous_root: [1; 32],
+            root: [2; 32],
+            bundles: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn block_proof_round_trips() {
+        let bytes = block_proof().to_bytes().unwrap();
+        assert!(BlockProof::is_block_proof(&bytes));
+
+        let decoded = BlockProof::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.height, 7);
+        assert_eq!(decoded.previous_root, [1; 32]);
+        assert_eq!(decoded.root, [2; 32]);
+        assert!(decoded.bundles.is_empty());
+        decoded.verify(false).unwrap();
+    }
+
+    #[test]
+    fn block_proof_is_not_a_proof_bundle() {
+        let mut bytes = block_proof().to_bytes().unwrap();
+        assert!(ProofBundle::from_bytes(&bytes).is_err());
+
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(BlockProof::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Parser;
+use risc0_zkvm::sha::Digest;
+use spin_primitives::AccountId;
+
+use std::path::PathBuf;
+
+use spin_verifier::{BlockProof, CallProof, ProofBundle, DEV_MODE_ENV};
+
+#[derive(Parser)]
+#[command(name = "spin_verify", about = "Offline verifier of Spin proof bundles")]
+struct Args {
+    /// Proof bundle file of a call, or block proof file
+    bundle: PathBuf,
+
+    /// Expected image ID of a contract as `<CONTRACT>=<HEX>`, may be repeated
+    #[arg(long = "image-id", value_parser = parse_image_id)]
+    image_ids: Vec<(AccountId, Digest)>,
+
+    /// Accept the fake receipts of dev mode, never use it for bundles of a production chain
+    #[arg(long, env = DEV_MODE_ENV)]
+    dev_mode: bool,
+}
+
+fn parse_image_id(arg: &str) -> Result<(AccountId, Digest)> {
+    let (contract, image_id) = arg
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected <CONTRACT>=<HEX>"))?;
+    let bytes = hex::decode(image_id.trim_start_matches("0x"))?;
+    if bytes.len() != 32 {
+        bail!("Image ID must be 32 bytes");
+    }
+    let mut words = [0u32; 8];
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into()?);
+    }
+
+    Ok((contract.parse()?, Digest::from(words)))
+}
+
+fn print_call(call_proof: &CallProof, depth: usize) {
+    let call = call_proof.call();
+    let outcome = match call_proof {
+        CallProof::Proven(_) => String::new(),
+        CallProof::Failed(_) => match call.committment().ok().and_then(|c| c.error) {
+            Some(error) => format!(" failed: {}", error),
+            None => String::from(" failed"),
+        },
+    };
+    println!(
+        "{}{}.{} image {} gas {}{}",
+        "  ".repeat(depth),
+        call.contract(),
+        call.call.method,
+        call.image_id,
+        call.env.attached_gas,
+        outcome
+    );
+    for call in call.calls.iter() {
+        print_call(call, depth + 1);
+    }
+}
+
+/// Checks that the contracts ran the expected images, if they ran.
+fn check_image_ids(bundle: &ProofBundle, expected: &[(AccountId, Digest)]) -> Result<()> {
+    let image_ids = bundle.image_ids()?;
+    for (contract, expected) in expected.iter() {
+        match image_ids.get(contract) {
+            Some(image_id) if image_id != expected => bail!(
+                "Contract {} ran image {}, expected {}",
+                contract,
+                image_id,
+                expected
+            ),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn verify_block(block: BlockProof, args: &Args) -> Result<()> {
+    block.verify(args.dev_mode)?;
+    for bundle in block.bundles.iter() {
+        check_image_ids(bundle, &args.image_ids)?;
+    }
+
+    println!(
+        "Block proof is valid, {} calls in block {}",
+        block.bundles.len(),
+        block.height
+    );
+    println!("previous root: {}", hex::encode(block.previous_root));
+    println!("root: {}", hex::encode(block.root));
+    if block.is_fake() {
+        println!("Receipts are fake, the block proof was made in dev mode");
+    }
+    for bundle in block.bundles {
+        println!("signed by {}", bundle.call.env.signer);
+        print_call(&CallProof::Proven(bundle.call), 1);
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let bytes = std::fs::read(&args.bundle)
+        .with_context(|| format!("Can't read proof bundle {:?}", args.bundle))?;
+    if BlockProof::is_block_proof(&bytes) {
+        let block = BlockProof::from_bytes(&bytes)
+            .with_context(|| format!("Invalid block proof {:?}", args.bundle))?;
+        return verify_block(block, &args);
+    }
+
+    let bundle = ProofBundle::from_bytes(&bytes)
+        .with_context(|| format!("Invalid proof bundle {:?}", args.bundle))?;
+    bundle.verify(args.dev_mode)?;
+
+    let image_ids = bundle.image_ids()?;
+    for (contract, expected) in args.image_ids.iter() {
+        match image_ids.get(contract) {
+            Some(image_id) if image_id == expected => {}
+            Some(image_id) => bail!(
+                "Contract {} ran image {}, expected {}",
+                contract,
+                image_id,
+                expected
+            ),
+            None => bail!("Contract {} didn't run in the proven call", contract),
+        }
+    }
+
+    let committment = bundle.call.committment()?;
+    println!(
+        "Proof bundle is valid, signed by {} at block {}",
+        bundle.call.env.signer, bundle.call.env.block.height
+    );
+    if bundle.call.is_fake() {
+        println!("Receipts are fake, the bundle was made in dev mode");
+    }
+    print_call(&CallProof::Proven(bundle.call), 0);
+    println!("output: {}", hex::encode(committment.output));
+
+    Ok(())
+}
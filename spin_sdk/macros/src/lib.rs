@@ -49,7 +49,7 @@ pub fn contract(_: TokenStream, input: TokenStream) -> TokenStream {
                 #(#methods) *
 
                 _ => {
-                    panic!("Unknown method name");
+                    env::fail(spin_sdk::spin_primitives::CallError::MethodNotFound(call.method.clone()));
                 }
             }
         }
@@ -4,14 +4,21 @@ use risc0_zkvm::{
     serde::from_slice,
     sha::rust_crypto::{Digest, Sha256},
 };
-use std::sync::Mutex;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
 
 use spin_primitives::{
     syscalls::{
-        GetStorageResponse, SetStorageRequest, CROSS_CONTRACT_CALL, GET_ACCOUNT_MAPPING,
-        GET_ENV_CALL, GET_STORAGE_CALL, SET_STORAGE_CALL,
+        AsyncCallRequest, GetStorageResponse, IterStoragePrefixRequest, IterStoragePrefixResponse,
+        ScheduleCallRequest, SetStorageRequest, ASYNC_CROSS_CONTRACT_CALL, CALL_RESPONSE,
+        CROSS_CONTRACT_CALL, GET_ACCOUNT_MAPPING, GET_ENV_CALL, GET_REVERSE_ACCOUNT_MAPPING,
+        GET_STORAGE_CALL, ITER_STORAGE_PREFIX_CALL, REMOVE_STORAGE_CALL, SCHEDULE_CALL,
+        SET_STORAGE_CALL, TRY_CROSS_CONTRACT_CALL,
     },
-    AccountId, CallEnv, ContractCall, ExecutionCommittment,
+    AccountId, CallEnv, CallError, Callback, ContractCall, ExecutionCommittment, FunctionCall,
+    Schedule,
 };
 
 static CALL_ENV: Lazy<Mutex<CallEnv>> = Lazy::new(|| Mutex::new(load_env_syscall()));
@@ -22,6 +29,17 @@ static CALL_ENV: Lazy<Mutex<CallEnv>> = Lazy::new(|| Mutex::new(load_env_syscall
 
 static CROSS_CALLS_HASHES: Lazy<Mutex<Vec<[u8; 32]>>> = Lazy::new(|| Mutex::new(Vec::new()));
 
+static CALL_HASH: Lazy<Mutex<Option<[u8; 32]>>> = Lazy::new(|| Mutex::new(None));
+
+/// Set once the call committed its output or failure, the journal holds a single committment.
+static COMMITTED: AtomicBool = AtomicBool::new(false);
+
+/// Records the call being executed, which the committment is bound to. Called by the entrypoint.
+#[doc(hidden)]
+pub fn set_call(call: &FunctionCall) {
+    *CALL_HASH.lock().unwrap() = Some(call.hash());
+}
+
 /// Loads the call environment from the host.
 pub fn load_env_syscall() -> CallEnv {
     let mut response = [0u32; 512]; // TODO: make this dynamic
@@ -47,6 +65,41 @@ pub fn contract() -> AccountId {
     CALL_ENV.lock().unwrap().contract.clone()
 }
 
+/// Returns the height of the current block
+pub fn block_height() -> u64 {
+    CALL_ENV.lock().unwrap().block.height
+}
+
+/// Returns the timestamp of the current block in milliseconds since the Unix epoch
+pub fn block_timestamp() -> u64 {
+    CALL_ENV.lock().unwrap().block.timestamp
+}
+
+/// Words of the buffer a cross-contract call response is first written to.
+const CALL_RESPONSE_WORDS: usize = 256;
+
+/// Makes a cross-contract call syscall and returns the encoded response.
+///
+/// The host returns the length of the response, a response larger than the buffer is fetched
+/// with a buffer of its length.
+fn call_syscall(call: ContractCall, fallible: bool) -> Vec<u32> {
+    let syscall = if fallible {
+        TRY_CROSS_CONTRACT_CALL
+    } else {
+        CROSS_CONTRACT_CALL
+    };
+
+    let mut response = vec![0u32; CALL_RESPONSE_WORDS];
+    let len = risc0_zkvm::guest::env::syscall(syscall, call.into_bytes().as_slice(), &mut response)
+        .0 as usize;
+    if len > response.len() {
+        response = vec![0u32; len];
+        risc0_zkvm::guest::env::syscall(CALL_RESPONSE, &[], &mut response);
+    }
+    response.truncate(len);
+    response
+}
+
 /// Makes a cross-contract call
 pub fn cross_contract_call<T: borsh::BorshSerialize, O: borsh::BorshDeserialize>(
     account: AccountId,
@@ -55,14 +108,7 @@ pub fn cross_contract_call<T: borsh::BorshSerialize, O: borsh::BorshDeserialize>
     args: T,
 ) -> O {
     let call = ContractCall::new(account, method, args, attached_gas);
-
-    let mut response = [0u32; 32]; // TODO: make this dynamic
-
-    risc0_zkvm::guest::env::syscall(
-        CROSS_CONTRACT_CALL,
-        call.into_bytes().as_slice(),
-        &mut response,
-    );
+    let response = call_syscall(call, false);
 
     let response: Vec<u8> =
         risc0_zkvm::serde::from_slice(&response).expect("Expected to deserialize");
@@ -78,6 +124,85 @@ pub fn cross_contract_call<T: borsh::BorshSerialize, O: borsh::BorshDeserialize>
         .unwrap()
 }
 
+/// Makes a cross-contract call, returning the error if the callee fails.
+///
+/// State changes of a failed callee are reverted. The failure is recorded in the cross calls
+/// hashes as the hash of the Borsh-encoded `Err`, so it is proven like a successful call.
+///
+/// Only failures the callee committed, with [`fail`] or by panicking, are returned: a callee which
+/// runs out of gas, doesn't exist or is nested too deep leaves no receipt, so the caller fails
+/// with the error too.
+pub fn try_cross_contract_call<T: borsh::BorshSerialize, O: borsh::BorshDeserialize>(
+    account: AccountId,
+    method: String,
+    attached_gas: u64,
+    args: T,
+) -> Result<O, CallError> {
+    let call = ContractCall::new(account, method, args, attached_gas);
+    let response = call_syscall(call, true);
+
+    let response: Vec<u8> =
+        risc0_zkvm::serde::from_slice(&response).expect("Expected to deserialize");
+    let result: Result<Vec<u8>, CallError> =
+        BorshDeserialize::deserialize(&mut response.as_slice()).expect("Expected to deserialize");
+
+    let algorithm = &mut risc0_zkvm::sha::rust_crypto::Sha256::default();
+    match &result {
+        Ok(journal) => algorithm.update(journal),
+        Err(_) => algorithm.update(&response),
+    }
+    let response_hash = algorithm.finalize_reset().as_slice().try_into().unwrap();
+    CROSS_CALLS_HASHES.lock().unwrap().push(response_hash);
+
+    result.map(|journal| {
+        ExecutionCommittment::try_from_bytes(journal)
+            .unwrap()
+            .try_deserialize_output()
+            .unwrap()
+    })
+}
+
+/// Schedules a cross-contract call, executed separately after the current execution is committed.
+///
+/// If `callback` is set, its method is called on the current contract with the
+/// `PromiseResult` of the call followed by the callback arguments.
+pub fn async_cross_contract_call<T: borsh::BorshSerialize>(
+    account: AccountId,
+    method: String,
+    attached_gas: u64,
+    args: T,
+    callback: Option<Callback>,
+) {
+    let request = AsyncCallRequest {
+        call: ContractCall::new(account, method, args, attached_gas),
+        callback,
+    };
+
+    let to_host = borsh::BorshSerialize::try_to_vec(&request).expect("Expected to serialize");
+
+    risc0_zkvm::guest::env::syscall(ASYNC_CROSS_CONTRACT_CALL, &to_host, &mut []);
+}
+
+/// Schedules a cross-contract call to run at future blocks, optionally recurring.
+///
+/// The attached gas of all runs is charged to the current call right away.
+pub fn schedule_call<T: borsh::BorshSerialize>(
+    account: AccountId,
+    method: String,
+    attached_gas: u64,
+    args: T,
+    schedule: Schedule,
+) {
+    let request = ScheduleCallRequest {
+        call: ContractCall::new(account, method, args, attached_gas),
+        schedule,
+    };
+
+    let to_host = borsh::BorshSerialize::try_to_vec(&request).expect("Expected to serialize");
+
+    risc0_zkvm::guest::env::syscall(SCHEDULE_CALL, &to_host, &mut []);
+}
+
 pub fn get_state<T: BorshDeserialize>(key: String) -> Option<T> {
     let mut response = [0u32; 65536];
 
@@ -127,6 +252,57 @@ pub fn set_state<T: borsh::BorshSerialize>(key: String, data: T) {
     risc0_zkvm::guest::env::syscall(SET_STORAGE_CALL, &to_host, &mut []);
 }
 
+/// Removes the value stored under the key, if any
+pub fn remove_state(key: String) {
+    risc0_zkvm::guest::env::syscall(REMOVE_STORAGE_CALL, &key.into_bytes(), &mut []);
+}
+
+/// Returns a page of at most `limit` values whose keys start with `prefix`, sorted by key,
+/// and the cursor to pass for the next page, `None` after the last one.
+pub fn iter_prefix<T: BorshDeserialize>(
+    prefix: String,
+    cursor: Option<String>,
+    limit: u32,
+) -> (Vec<(String, T)>, Option<String>) {
+    let request = IterStoragePrefixRequest {
+        prefix,
+        cursor,
+        limit,
+    };
+
+    let mut response = [0u32; 65536];
+
+    risc0_zkvm::guest::env::syscall(
+        ITER_STORAGE_PREFIX_CALL,
+        &borsh::BorshSerialize::try_to_vec(&request).expect("Expected to serialize"),
+        &mut response,
+    );
+
+    let response: Vec<u8> =
+        risc0_zkvm::serde::from_slice(&response).expect("Expected to deserialize");
+
+    let response: IterStoragePrefixResponse =
+        BorshDeserialize::try_from_slice(&response).expect("Expected to deserialize");
+
+    let algorithm = &mut Sha256::default();
+    algorithm.update(
+        &borsh::BorshSerialize::try_to_vec(&response.entries).expect("Expected to serialize"),
+    );
+    assert!(response.hash == algorithm.finalize_reset().as_slice());
+
+    let entries = response
+        .entries
+        .into_iter()
+        .map(|(key, value)| {
+            let value = BorshDeserialize::deserialize(&mut value.as_slice())
+                .expect("Expected to deserialize");
+            (key, value)
+        })
+        .collect();
+
+    (entries, response.cursor)
+}
+
 // pub fn set_final_state<T: borsh::BorshSerialize>(state: T) {
 //     let mut final_state = FINAL_STATE.lock().unwrap();
 //     *final_state = Some(borsh::BorshSerialize::try_to_vec(&state).expect("Expected to serialize"));
@@ -143,20 +319,83 @@ pub fn commit<T: borsh::BorshSerialize>(output: T) {
 
     // let initial_state_hash = INITIAL_STATE_HASH.lock().unwrap().clone();
 
-    let cross_calls_hashes = CROSS_CALLS_HASHES.lock().unwrap().clone();
-
-    let committment = ExecutionCommittment {
+    let committment = ExecutionCommittment::new(
+        &CALL_ENV.lock().unwrap(),
+        call_hash(),
         output,
-        cross_calls_hashes,
-        initial_state_hash: None,
-        final_state_hash: None,
-    };
+        CROSS_CALLS_HASHES.lock().unwrap().clone(),
+    );
+
+    commit_committment(&committment)
+}
+
+/// Fails the call with the error, instead of committing an output.
+///
+/// Unlike a panic, the failure is committed, so it is proven: the runtime reverts the state
+/// changes of the call and returns the error to a caller using `try_cross_contract_call`.
+pub fn fail(error: CallError) {
+    let committment = ExecutionCommittment::failed(
+        &CALL_ENV.lock().unwrap(),
+        call_hash(),
+        error,
+        CROSS_CALLS_HASHES.lock().unwrap().clone(),
+    );
 
+    commit_committment(&committment)
+}
+
+fn commit_committment(committment: &ExecutionCommittment) {
+    COMMITTED.store(true, Ordering::SeqCst);
     risc0_zkvm::guest::env::commit_slice(
-        &borsh::BorshSerialize::try_to_vec(&committment).expect("Expected to serialize"),
+        &borsh::BorshSerialize::try_to_vec(committment).expect("Expected to serialize"),
     )
 }
 
+/// Commits the failure of a call which panics and stops the guest, so that the panic is proven
+/// like a failure returned with [`fail`]. Called by the entrypoint.
+///
+/// A panic before the call is loaded, after it committed, or while the state of the SDK is
+/// locked can't be committed; the guest then stops without a receipt, as it would without the
+/// hook.
+#[doc(hidden)]
+pub fn set_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        if COMMITTED.load(Ordering::SeqCst) {
+            return;
+        }
+        // The hook runs where the panic happened, so a lock held there can't be taken again.
+        let (call_hash, cross_calls_hashes) =
+            match (CALL_HASH.try_lock(), CROSS_CALLS_HASHES.try_lock()) {
+                (Ok(call_hash), Ok(cross_calls_hashes)) => (*call_hash, cross_calls_hashes.clone()),
+                _ => return,
+            };
+        let call_hash = match call_hash {
+            Some(call_hash) => call_hash,
+            None => return,
+        };
+        let env = match Lazy::get(&CALL_ENV).map(Mutex::try_lock) {
+            Some(Ok(env)) => env.clone(),
+            Some(Err(_)) => return,
+            None => load_env_syscall(),
+        };
+
+        commit_committment(&ExecutionCommittment::failed(
+            &env,
+            call_hash,
+            CallError::Panic(info.to_string()),
+            cross_calls_hashes,
+        ));
+        risc0_zkvm::guest::env::pause();
+    }));
+}
+
+fn call_hash() -> [u8; 32] {
+    CALL_HASH
+        .lock()
+        .unwrap()
+        .expect("Expected the call to be set by the entrypoint")
+}
+
 /// Get EVM address by AccountId
 pub fn get_evm_address(account_id: AccountId) -> eth_primitive_types::H160 {
     let mut response = [0u32; 20];
@@ -172,3 +411,19 @@ pub fn get_evm_address(account_id: AccountId) -> eth_primitive_types::H160 {
 
     eth_primitive_types::H160::from_slice(&response)
 }
+
+/// Get AccountId which registered the EVM address
+pub fn get_account_id(address: eth_primitive_types::H160) -> Option<AccountId> {
+    let mut response = [0u32; 128];
+
+    risc0_zkvm::guest::env::syscall(
+        GET_REVERSE_ACCOUNT_MAPPING,
+        address.as_bytes(),
+        &mut response,
+    );
+
+    let response: Vec<u8> =
+        risc0_zkvm::serde::from_slice(&response).expect("Expected to deserialize");
+
+    BorshDeserialize::deserialize(&mut response.as_slice()).expect("Expected to deserialize")
+}
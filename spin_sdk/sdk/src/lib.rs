@@ -13,10 +13,12 @@ macro_rules! entrypoint {
         mod zkvm_generated_main {
             #[no_mangle]
             fn main() {
+                spin_sdk::env::set_panic_hook();
                 let call = spin_sdk::spin_primitives::FunctionCall::try_from_bytes(
                     risc0_zkvm::guest::env::read(),
                 )
                 .expect("Expected to deserialize");
+                spin_sdk::env::set_call(&call);
                 super::ZKVM_ENTRY(call)
             }
         }